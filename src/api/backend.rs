@@ -0,0 +1,233 @@
+//! 可插拔的推理后端抽象：`ModelPool` 过去直接硬编码调用
+//! `engine::llama_cpp::LlamaEngine::new`，本模块把"根据 `Model` 的声明类型
+//! 选择并构造一个后端"这件事抽出来，变成一个按名称注册的 `BackendRegistry`。
+//!
+//! 目前注册了两个后端：本地的 `LlamaEngine`（通过 `LocalBackend` 适配），以及
+//! 一个把请求转发给上游 OpenAI 兼容服务器的 `RemoteOpenAiBackend`，复用
+//! `RPCClient` 里已经用过的阻塞式 `reqwest` 客户端模式。
+
+use crate::{
+    discover::{Model, ModelType},
+    engine::{EngineCallback, EngineConfig},
+};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::sync::Arc;
+
+/// 对"能执行推理/embedding 的后端"的抽象，不关心背后是本地引擎还是远程服务器。
+/// 方法签名刻意与 `engine::EngineBackend` 保持一致，方便把现有引擎适配进来。
+pub trait TransformBackend: Send + Sync {
+    fn infer(
+        &self,
+        conversation_id: Option<&str>,
+        prompt: &str,
+        option: Option<&EngineConfig>,
+        callback: Option<EngineCallback>,
+    ) -> Result<String>;
+    fn get_model_info(&self) -> Model;
+    fn count_tokens(&self, text: &str) -> usize;
+    fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+type BackendConstructor =
+    fn(&EngineConfig, &Model) -> Result<Arc<dyn TransformBackend>, Box<dyn std::error::Error>>;
+
+/// 按名称注册后端构造函数。`ModelPool` 用 `backend_name_for` 把一个 `Model`
+/// 映射到已注册的后端名称，再用 `build` 构造出实际可用的后端实例。
+pub struct BackendRegistry {
+    backends: HashMap<&'static str, BackendConstructor>,
+}
+
+impl BackendRegistry {
+    pub fn new() -> Self {
+        let mut backends: HashMap<&'static str, BackendConstructor> = HashMap::new();
+
+        #[cfg(feature = "engine-llama-cpp")]
+        backends.insert("local", |config, model| {
+            let engine = crate::engine::llama_cpp::LlamaEngine::new(config, model)?;
+            Ok(Arc::new(LocalBackend(engine)) as Arc<dyn TransformBackend>)
+        });
+
+        backends.insert("openai_remote", |_config, model| {
+            Ok(Arc::new(RemoteOpenAiBackend::new(model)?) as Arc<dyn TransformBackend>)
+        });
+
+        Self { backends }
+    }
+
+    /// 根据 `Model` 的声明类型选出应该使用哪个已注册的后端名称。
+    pub fn backend_name_for(model: &Model) -> &'static str {
+        match model.format {
+            ModelType::Remote => "openai_remote",
+            ModelType::Gguf | ModelType::Transformers => "local",
+        }
+    }
+
+    pub fn build(
+        &self,
+        config: &EngineConfig,
+        model: &Model,
+    ) -> Result<Arc<dyn TransformBackend>, Box<dyn std::error::Error>> {
+        let name = Self::backend_name_for(model);
+        let constructor = self
+            .backends
+            .get(name)
+            .ok_or_else(|| format!("No backend registered for '{}'", name))?;
+        constructor(config, model)
+    }
+}
+
+impl Default for BackendRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 把现有的 `EngineBackend` 实现（目前是 `LlamaEngine`）接到
+/// `TransformBackend` 接口上，让它能被 `BackendRegistry` 统一管理。
+#[cfg(feature = "engine-llama-cpp")]
+struct LocalBackend(crate::engine::llama_cpp::LlamaEngine);
+
+#[cfg(feature = "engine-llama-cpp")]
+impl TransformBackend for LocalBackend {
+    fn infer(
+        &self,
+        conversation_id: Option<&str>,
+        prompt: &str,
+        option: Option<&EngineConfig>,
+        callback: Option<EngineCallback>,
+    ) -> Result<String> {
+        use crate::engine::EngineBackend;
+        self.0.infer(conversation_id, prompt, option, callback)
+    }
+
+    fn get_model_info(&self) -> Model {
+        use crate::engine::EngineBackend;
+        self.0.get_model_info()
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        use crate::engine::EngineBackend;
+        self.0.count_tokens(text)
+    }
+
+    fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        use crate::engine::EngineBackend;
+        self.0.embed(inputs)
+    }
+}
+
+/// 把推理/embedding 请求转发给一台上游的、OpenAI 兼容的 HTTP 服务器。
+/// `model.path` 存放该服务器的 base URL，`model.name` 是转发时使用的远程模型 id。
+struct RemoteOpenAiBackend {
+    client: reqwest::blocking::Client,
+    base_url: String,
+    model_info: Model,
+}
+
+impl RemoteOpenAiBackend {
+    fn new(model: &Model) -> Result<Self, Box<dyn std::error::Error>> {
+        let base_url = model
+            .path
+            .to_str()
+            .ok_or("remote model base URL must be valid UTF-8")?
+            .trim_end_matches('/')
+            .to_string();
+        Ok(RemoteOpenAiBackend {
+            client: reqwest::blocking::Client::new(),
+            base_url,
+            model_info: model.clone(),
+        })
+    }
+}
+
+impl TransformBackend for RemoteOpenAiBackend {
+    fn infer(
+        &self,
+        // 上游服务器自己管理会话状态，这里没有本地 KV cache 可复用，忽略该参数。
+        _conversation_id: Option<&str>,
+        prompt: &str,
+        option: Option<&EngineConfig>,
+        mut callback: Option<EngineCallback>,
+    ) -> Result<String> {
+        let url = format!("{}/v1/chat/completions", self.base_url);
+        let streaming = callback.is_some();
+        let body = serde_json::json!({
+            "model": self.model_info.name,
+            "messages": [{"role": "user", "content": prompt}],
+            "temperature": option.map(|o| o.temperature),
+            "stream": streaming,
+        });
+        let response = self.client.post(&url).json(&body).send()?;
+
+        if !streaming {
+            let value: serde_json::Value = response.json()?;
+            return Ok(value["choices"][0]["message"]["content"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string());
+        }
+
+        // 远程服务器没有本地的 SSE 解析基础设施可以复用，这里手动按行读取
+        // `data: {...}` 事件，并把每个增量 token 转发给调用方的回调。
+        let mut full_text = String::new();
+        for line in std::io::BufReader::new(response).lines() {
+            let line = line?;
+            let Some(data) = line.strip_prefix("data: ") else {
+                continue;
+            };
+            if data == "[DONE]" {
+                break;
+            }
+            let Ok(chunk) = serde_json::from_str::<serde_json::Value>(data) else {
+                continue;
+            };
+            let Some(delta) = chunk["choices"][0]["delta"]["content"].as_str() else {
+                continue;
+            };
+            full_text.push_str(delta);
+            if let Some(cb) = callback.as_mut() {
+                if cb(delta.to_string()) {
+                    break;
+                }
+            }
+        }
+        Ok(full_text)
+    }
+
+    fn get_model_info(&self) -> Model {
+        self.model_info.clone()
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        // 远程后端没有本地分词器可用，用空白词数粗略近似，仅用于大致的用量统计。
+        text.split_whitespace().count()
+    }
+
+    fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/v1/embeddings", self.base_url);
+        let body = serde_json::json!({
+            "model": self.model_info.name,
+            "input": inputs,
+        });
+        let response: serde_json::Value = self.client.post(&url).json(&body).send()?.json()?;
+        let data = response["data"]
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("remote backend returned no embeddings"))?;
+        // 上游服务器是按 index 返回的，理论上已经有序，但不依赖这个假设。
+        let mut items: Vec<(u64, Vec<f32>)> = data
+            .iter()
+            .map(|item| {
+                let index = item["index"].as_u64().unwrap_or(0);
+                let embedding = item["embedding"]
+                    .as_array()
+                    .map(|values| values.iter().map(|v| v.as_f64().unwrap_or(0.0) as f32).collect())
+                    .unwrap_or_default();
+                (index, embedding)
+            })
+            .collect();
+        items.sort_by_key(|(index, _)| *index);
+        Ok(items.into_iter().map(|(_, embedding)| embedding).collect())
+    }
+}
@@ -0,0 +1,12 @@
+//! OpenAI 兼容的 HTTP API：`server.rs` 起一个共享 `ModelPool` 的 actix-web 服务，
+//! 把实际的路由挂到 `openai.rs`（`/v1/...`）和 `rllama_api.rs`（`/rllama/...`）
+//! 上，`ui.rs` 再在根路径挂上零配置的内置 playground/arena 静态页面。
+
+mod backend;
+mod openai;
+mod pool;
+mod rllama_api;
+mod server;
+mod ui;
+
+pub use server::start_api_server;
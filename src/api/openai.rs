@@ -1,16 +1,123 @@
 // src/api/openai_compatible.rs
 use super::server::AppState;
-use crate::template::Message;
+use crate::template::{Message, MessageContent};
 use crate::{discover::MODEL_DISCOVERER, engine::EngineConfig};
 use actix_web::web::Bytes;
 use actix_web::{HttpResponse, Result as ActixResult, web};
+use rayon::prelude::*;
 use serde::Serialize;
 use serde_json::{Value, json};
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio_stream::StreamExt;
 use uuid::Uuid;
 
+/// 检索增强时拼进 system prompt 的历史段落条数，和 `chat.rs` 里 CLI 用的一致。
+const RAG_CONTEXT_TOP_K: usize = 3;
+
+/// 流式生成时增量识别 `<tool_call>...</tool_call>` 块，避免把命中了工具调用
+/// 标签的半截文本当普通 content token 泄露给客户端。和非流式路径用的
+/// `template::parse_tool_calls` 是同一套标签格式，只是这里要在 token 一个个
+/// 到达的过程中增量处理，而不是等一次性拿到完整文本。
+struct ToolCallStreamBuffer {
+    acc: String,
+    emitted: usize,
+    tag_open: Option<usize>,
+}
+
+/// `ToolCallStreamBuffer::push`/`finish` 产出的一段增量：要么是该原样转发给
+/// 客户端的普通文本，要么是一个已经解析完整的工具调用。
+enum StreamToken {
+    Content(String),
+    ToolCall(ToolCallDto),
+}
+
+impl ToolCallStreamBuffer {
+    const OPEN_TAG: &'static str = "<tool_call>";
+    const CLOSE_TAG: &'static str = "</tool_call>";
+
+    fn new() -> Self {
+        ToolCallStreamBuffer {
+            acc: String::new(),
+            emitted: 0,
+            tag_open: None,
+        }
+    }
+
+    /// 喂入新到达的一个 token，返回这次调用里可以确定下来、能安全转发的增量。
+    fn push(&mut self, tok: &str) -> Vec<StreamToken> {
+        self.acc.push_str(tok);
+        let mut out = Vec::new();
+        loop {
+            match self.tag_open {
+                None => {
+                    if let Some(rel) = self.acc[self.emitted..].find(Self::OPEN_TAG) {
+                        let start = self.emitted + rel;
+                        if start > self.emitted {
+                            out.push(StreamToken::Content(self.acc[self.emitted..start].to_string()));
+                        }
+                        self.emitted = start;
+                        self.tag_open = Some(start);
+                        continue;
+                    }
+                    // 还没见到完整的开标签：留住末尾可能是标签前缀的字节，
+                    // 避免标签被 token 切分导致漏判。
+                    let hold_back = Self::OPEN_TAG.len() - 1;
+                    let safe_len = self.acc.len().saturating_sub(hold_back).max(self.emitted);
+                    if safe_len > self.emitted {
+                        out.push(StreamToken::Content(self.acc[self.emitted..safe_len].to_string()));
+                        self.emitted = safe_len;
+                    }
+                    break;
+                }
+                Some(start) => {
+                    let Some(rel) = self.acc[start..].find(Self::CLOSE_TAG) else {
+                        break;
+                    };
+                    let close_end = start + rel + Self::CLOSE_TAG.len();
+                    let block = &self.acc[start..close_end];
+                    let (_, calls) = crate::template::parse_tool_calls(block);
+                    // 解析失败（标签里不是合法的工具调用）就当它没出现过，整块丢弃；
+                    // 这和 `parse_tool_calls` 不同，后者会把畸形块原样留在剩余文本里。
+                    if let Some(call) = calls.into_iter().next() {
+                        out.push(StreamToken::ToolCall(ToolCallDto {
+                            id: call.id.unwrap_or_else(|| Uuid::new_v4().to_string()),
+                            call_type: "function".to_string(),
+                            function: ToolCallFunctionDto {
+                                name: call.function.name,
+                                arguments: call
+                                    .function
+                                    .arguments
+                                    .map(|v| v.to_string())
+                                    .unwrap_or_default(),
+                            },
+                        }));
+                    }
+                    self.emitted = close_end;
+                    self.tag_open = None;
+                }
+            }
+        }
+        out
+    }
+
+    /// 生成结束时 flush 掉还没能确定下来的末尾内容。正常情况下只是 `push`
+    /// 为了防止标签被切分而留的几个字节；如果流恰好在一个 `<tool_call>`
+    /// 标签中途结束（模型被截断），已经没有更多 token 会到来把它补完，这时
+    /// 如实把原始文本发出去，好过像整段文本解析那样直接丢弃。
+    fn finish(&mut self) -> Vec<StreamToken> {
+        if self.emitted < self.acc.len() {
+            let remainder = self.acc[self.emitted..].to_string();
+            self.emitted = self.acc.len();
+            vec![StreamToken::Content(remainder)]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
 // OpenAI 兼容的请求结构体
 #[derive(serde::Deserialize, Debug)]
 pub struct CompletionRequest {
@@ -47,14 +154,191 @@ pub struct ChatCompletionRequest {
     pub presence_penalty: Option<f32>,
     pub frequency_penalty: Option<f32>,
     pub logit_bias: Option<Value>,
+    /// Function/tool definitions the model may call, OpenAI `tools` shape.
+    #[serde(default)]
+    pub tools: Option<Vec<ToolDefinition>>,
+    #[serde(default)]
+    pub tool_choice: Option<Value>,
+    /// Id of a conversation created via `/v1/conversations`. When set, the
+    /// local llama.cpp backend keeps the KV cache for this id warm across
+    /// requests instead of re-decoding the whole prompt every time.
+    #[serde(default)]
+    pub conversation_id: Option<String>,
 }
 
 #[derive(serde::Deserialize, Debug, Clone, Serialize)]
 pub struct ChatMessage {
     pub role: String,
-    pub content: String,
+    // `MessageContent` is `#[serde(untagged)]` over `String`/`Vec<ContentPart>`, so this
+    // still accepts (and emits) a plain string for ordinary text messages; clients that
+    // send the OpenAI vision array form (`[{"type": "text", ...}, {"type": "image_url", ...}]`)
+    // now actually reach `MessageContent::Parts` instead of failing to deserialize.
+    #[serde(default)]
+    pub content: MessageContent,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub tool_calls: Option<Vec<ToolCallDto>>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub tool_call_id: Option<String>,
+}
+
+// OpenAI 的工具定义结构体
+#[derive(serde::Deserialize, Debug, Clone, Serialize)]
+pub struct ToolDefinition {
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: FunctionDefinition,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Serialize)]
+pub struct FunctionDefinition {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Value>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Serialize)]
+pub struct ToolCallDto {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub call_type: String,
+    pub function: ToolCallFunctionDto,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Serialize)]
+pub struct ToolCallFunctionDto {
+    pub name: String,
+    pub arguments: String,
+}
+
+impl ToolDefinition {
+    fn into_template_tool(self) -> crate::template::Tool {
+        let properties = self.function.parameters.as_ref().and_then(|params| {
+            params["properties"].as_object().map(|props| {
+                props
+                    .iter()
+                    .map(|(name, schema)| {
+                        (
+                            name.clone(),
+                            crate::template::ToolProperty {
+                                property_type: schema["type"]
+                                    .as_str()
+                                    .unwrap_or("string")
+                                    .to_string(),
+                                description: schema["description"]
+                                    .as_str()
+                                    .map(|s| s.to_string()),
+                                enum_values: schema["enum"].as_array().map(|values| {
+                                    values
+                                        .iter()
+                                        .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                                        .collect()
+                                }),
+                            },
+                        )
+                    })
+                    .collect()
+            })
+        });
+        let required = self
+            .function
+            .parameters
+            .as_ref()
+            .and_then(|params| params["required"].as_array())
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                    .collect()
+            });
+
+        crate::template::Tool {
+            tool_type: self.tool_type,
+            function: crate::template::Function {
+                name: self.function.name,
+                description: self.function.description,
+                parameters: Some(crate::template::FunctionParameters {
+                    param_type: "object".to_string(),
+                    properties,
+                    required,
+                }),
+                arguments: None,
+            },
+        }
+    }
+}
+
+/// 按 OpenAI `tool_choice` 语义裁剪渲染给模板的工具列表。
+/// `"none"` 关闭工具调用；`{"type": "function", "function": {"name": ..}}`
+/// 强制只保留指定的那个工具，其余取值（`"auto"`/`"required"`/缺省）原样透传。
+fn apply_tool_choice(
+    tools: Vec<crate::template::Tool>,
+    tool_choice: Option<&Value>,
+) -> Option<Vec<crate::template::Tool>> {
+    match tool_choice {
+        Some(Value::String(s)) if s == "none" => None,
+        Some(choice) => match choice["function"]["name"].as_str() {
+            Some(name) => Some(tools.into_iter().filter(|t| t.function.name == name).collect()),
+            None => Some(tools),
+        },
+        None => Some(tools),
+    }
+}
+
+/// 校验 `n`/`best_of` 是否在 `max_client_batch_size` 允许的范围内，并返回
+/// 实际要生成的候选数（`best_of`，没给就等于 `n`）。chat completions 没有
+/// `best_of` 这个参数，调用方直接把 `n` 当 `best_of` 传进来，校验就退化成只看 `n`。
+/// 不合法的组合都报成 `invalid_request_error`，和其余参数校验失败的响应形状保持一致。
+///
+/// `stream` 为 `true` 时不接受 `best_of > n`：多候选场景下 `best_of` 的意义
+/// 是"多生成几个、挑最好的返回"，但流式响应是逐 token 往外发的，已经发出去
+/// 的候选没法在生成完之后被悄悄替换掉，所以这个组合直接拒绝，和 OpenAI的
+/// 限制一致。
+fn validate_batch_size(
+    n: Option<u32>,
+    best_of: Option<u32>,
+    stream: bool,
+    max_batch: usize,
+) -> Result<u32, ErrorResponse> {
+    let n = n.unwrap_or(1).max(1);
+    let best_of = best_of.unwrap_or(n);
+    let invalid = |message: String| ErrorResponse {
+        error: ErrorInfo {
+            message,
+            error_type: "invalid_request_error".to_string(),
+            code: Some("invalid_request_error".to_string()),
+        },
+    };
+    if best_of < n {
+        return Err(invalid("best_of must be greater than or equal to n".to_string()));
+    }
+    if n as usize > max_batch || best_of as usize > max_batch {
+        return Err(invalid(format!(
+            "n must not exceed max_client_batch_size ({})",
+            max_batch
+        )));
+    }
+    if stream && best_of > n {
+        return Err(invalid("best_of is not supported when stream is true".to_string()));
+    }
+    Ok(best_of)
+}
+
+/// 把 OpenAI `logit_bias` 里字符串形式的 token id 解析成引擎需要的
+/// `HashMap<i32, f32>`；不是对象、或者某个键不能解析成 token id 的条目
+/// 直接丢弃，不影响其余 bias 生效。
+fn parse_logit_bias(logit_bias: Option<&Value>) -> std::collections::HashMap<i32, f32> {
+    logit_bias
+        .and_then(Value::as_object)
+        .map(|obj| {
+            obj.iter()
+                .filter_map(|(token_id, bias)| Some((token_id.parse().ok()?, bias.as_f64()? as f32)))
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
 // OpenAI 兼容的响应结构体
@@ -112,6 +396,19 @@ pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    /// Wall-clock time spent generating, in milliseconds (tllama extension, mirrors Ollama's `total_duration`).
+    pub total_duration_ms: u64,
+}
+
+impl Usage {
+    fn new(prompt_tokens: usize, completion_tokens: usize, elapsed: std::time::Duration) -> Self {
+        Usage {
+            prompt_tokens: prompt_tokens as u32,
+            completion_tokens: completion_tokens as u32,
+            total_tokens: (prompt_tokens + completion_tokens) as u32,
+            total_duration_ms: elapsed.as_millis() as u64,
+        }
+    }
 }
 
 // 流式响应结构体
@@ -122,6 +419,8 @@ pub struct StreamCompletionResponse {
     pub created: u64,
     pub model: String,
     pub choices: Vec<StreamCompletionChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
 
 #[derive(serde::Serialize)]
@@ -139,6 +438,8 @@ pub struct StreamChatCompletionResponse {
     pub created: u64,
     pub model: String,
     pub choices: Vec<StreamChatCompletionChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
 
 #[derive(serde::Serialize)]
@@ -211,6 +512,16 @@ pub async fn create_completion(
 ) -> ActixResult<HttpResponse> {
     let stream_requested = request.stream.unwrap_or(false);
     let model_name = request.model.clone();
+    let n = request.n.unwrap_or(1).max(1);
+    let best_of = match validate_batch_size(
+        request.n,
+        request.best_of,
+        stream_requested,
+        crate::config::APP_CONFIG.max_client_batch_size,
+    ) {
+        Ok(best_of) => best_of,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
+    };
 
     // 转换参数到引擎配置
     let engine_config = EngineConfig {
@@ -220,6 +531,10 @@ pub async fn create_completion(
         top_k: 40, // OpenAI 使用 top_p，但我们保留 top_k 作为默认
         top_p: request.top_p.unwrap_or(1.0),
         repeat_penalty: 1.0, // 默认不使用重复惩罚
+        stop: request.stop.clone().unwrap_or_default(),
+        presence_penalty: request.presence_penalty.unwrap_or(0.0),
+        frequency_penalty: request.frequency_penalty.unwrap_or(0.0),
+        ..Default::default()
     };
 
     let engine_arc = match data.model_pool.get_model(&model_name).await {
@@ -236,83 +551,119 @@ pub async fn create_completion(
     };
 
     if stream_requested {
+        // `validate_batch_size` 已经拒绝了 `best_of > n` 的流式请求，所以这里
+        // 就是 `n` 条互不依赖的候选，各自用自己的 `index` 往同一个 channel 里发
+        // token；最后一个收尾的候选负责发覆盖全部候选的 usage chunk。
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<StreamCompletionResponse>();
-        let prompt = request.prompt.clone();
         let model_name_clone = model_name.clone();
-        let engine_arc_clone = Arc::clone(&engine_arc);
-
-        tokio::task::spawn_blocking(move || {
-            let tx_tokens = tx.clone();
-            let model_name_clone2 = model_name_clone.clone();
-            let request_id = Uuid::new_v4().to_string();
-            let request_id_clone = request_id.clone();
-            let created = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
+        let prompt_tokens = engine_arc.count_tokens(&request.prompt);
+        let request_id = Uuid::new_v4().to_string();
+        let created = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let start = std::time::Instant::now();
+        let completion_tokens_total = Arc::new(AtomicUsize::new(0));
+        let pending = Arc::new(AtomicUsize::new(n as usize));
 
-            // 发送初始空响应（如果需要）
-            let _ = tx_tokens.send(StreamCompletionResponse {
+        // 每个候选各发一条只带 index、不带内容的起始 chunk
+        for index in 0..n {
+            let _ = tx.send(StreamCompletionResponse {
                 id: request_id.clone(),
                 object: "text_completion".to_string(),
                 created,
                 model: model_name_clone.clone(),
                 choices: vec![StreamCompletionChoice {
                     text: String::new(),
-                    index: 0,
+                    index,
                     logprobs: None,
                     finish_reason: None,
                 }],
+                usage: None,
             });
+        }
+
+        for index in 0..n {
+            let tx_tokens = tx.clone();
+            let tx_done = tx.clone();
+            let prompt = request.prompt.clone();
+            let model_name_clone = model_name_clone.clone();
+            let model_name_done = model_name_clone.clone();
+            let engine_arc_clone = Arc::clone(&engine_arc);
+            let engine_config = engine_config.clone();
+            let request_id_clone = request_id.clone();
+            let request_id_done = request_id.clone();
+            let completion_tokens_total = Arc::clone(&completion_tokens_total);
+            let pending = Arc::clone(&pending);
+
+            tokio::task::spawn_blocking(move || {
+                // 执行推理并流式发送响应（经典 completions 接口没有会话概念，不复用 KV cache）
+                let result = engine_arc_clone.infer(
+                    None,
+                    &prompt,
+                    Some(&engine_config),
+                    Some(Box::new(move |tok| {
+                        let response = StreamCompletionResponse {
+                            id: request_id_clone.clone(),
+                            object: "text_completion".to_string(),
+                            created,
+                            model: model_name_clone.clone(),
+                            choices: vec![StreamCompletionChoice {
+                                text: tok.into(),
+                                index,
+                                logprobs: None,
+                                finish_reason: None,
+                            }],
+                            usage: None,
+                        };
+                        // 回调返回值是“要不要停止生成”，不是“发送是否成功”：只有
+                        // 接收端已经断开（客户端提前关闭了连接）才需要喊停。
+                        tx_tokens.send(response).is_err()
+                    })),
+                );
 
-            let mut accumulated_text = String::new();
+                let completion_tokens = engine_arc_clone.count_tokens(&result.unwrap_or_default());
+                completion_tokens_total.fetch_add(completion_tokens, Ordering::SeqCst);
+
+                let _ = tx_done.send(StreamCompletionResponse {
+                    id: request_id_done.clone(),
+                    object: "text_completion".to_string(),
+                    created,
+                    model: model_name_done.clone(),
+                    choices: vec![StreamCompletionChoice {
+                        text: String::new(),
+                        index,
+                        logprobs: None,
+                        finish_reason: Some("stop".to_string()),
+                    }],
+                    usage: None,
+                });
 
-            // 执行推理并流式发送响应
-            let _ = engine_arc_clone.infer(
-                &prompt,
-                Some(&engine_config),
-                Some(Box::new(move |tok| {
-                    accumulated_text.push_str(&tok);
-                    let response = StreamCompletionResponse {
-                        id: request_id.clone(),
+                // 最后一个结束的候选负责发收尾的 usage chunk，覆盖整个请求的耗时
+                // 和全部候选加起来的 completion_tokens。
+                if pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+                    let _ = tx_done.send(StreamCompletionResponse {
+                        id: request_id_done,
                         object: "text_completion".to_string(),
                         created,
-                        model: model_name_clone.clone(),
-                        choices: vec![StreamCompletionChoice {
-                            text: tok.into(),
-                            index: 0,
-                            logprobs: None,
-                            finish_reason: None,
-                        }],
-                    };
-                    let a = tx_tokens.send(response);
-                    if a.is_err() {
-                        return false;
-                    }
-                    true
-                })),
-            );
-
-            // 发送结束信号
-            let _ = tx.send(StreamCompletionResponse {
-                id: request_id_clone,
-                object: "text_completion".to_string(),
-                created,
-                model: model_name_clone2,
-                choices: vec![StreamCompletionChoice {
-                    text: String::new(),
-                    index: 0,
-                    logprobs: None,
-                    finish_reason: Some("stop".to_string()),
-                }],
+                        model: model_name_done,
+                        choices: vec![],
+                        usage: Some(Usage::new(
+                            prompt_tokens,
+                            completion_tokens_total.load(Ordering::SeqCst),
+                            start.elapsed(),
+                        )),
+                    });
+                }
             });
-        });
+        }
 
         let stream = tokio_stream::wrappers::UnboundedReceiverStream::new(rx);
-        let stream = stream.map(|chunk| {
-            let json_str = serde_json::to_string(&chunk).unwrap();
-            Ok::<Bytes, actix_web::Error>(Bytes::from(format!("data: {}\n\n", json_str)))
-        });
+        let stream = stream
+            .map(|chunk| {
+                let json_str = serde_json::to_string(&chunk).unwrap();
+                Ok::<Bytes, actix_web::Error>(Bytes::from(format!("data: {}\n\n", json_str)))
+            })
+            .chain(tokio_stream::once(Ok(Bytes::from_static(
+                b"data: [DONE]\n\n",
+            ))));
 
         Ok(HttpResponse::Ok()
             .append_header(("Content-Type", "text/event-stream"))
@@ -320,39 +671,59 @@ pub async fn create_completion(
             .append_header(("Access-Control-Allow-Origin", "*"))
             .streaming(stream))
     } else {
-        // 非流式推理
-        match engine_arc.infer(&request.prompt, Some(&engine_config), None) {
-            Ok(text) => {
-                let response = CompletionResponse {
-                    id: Uuid::new_v4().to_string(),
-                    object: "text_completion".to_string(),
-                    created: SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-                    model: model_name,
-                    choices: vec![CompletionChoice {
-                        text,
-                        index: 0,
-                        logprobs: None,
-                        finish_reason: "stop".to_string(),
-                    }],
-                    usage: Usage {
-                        prompt_tokens: 0,     // 需要实际统计
-                        completion_tokens: 0, // 需要实际统计
-                        total_tokens: 0,
-                    },
-                };
-                Ok(HttpResponse::Ok().json(response))
+        // 非流式推理：`best_of` 条候选复用同一个已加载的 `engine_arc`、用 rayon
+        // 并发生成（和工具调用的并发 dispatch 是同一套模式），失败一条就整体
+        // 报错。引擎目前不对外暴露逐 token 的 logprob，没法真的按"累计对数概率"
+        // 排序，所以用文本长度近似——没被过早截断的候选通常更长，这是目前能拿到
+        // 的最接近的信号——挑出前 `n` 条作为 `choices` 返回。
+        let start = std::time::Instant::now();
+        let prompt_tokens = engine_arc.count_tokens(&request.prompt);
+        let results: Vec<anyhow::Result<String>> = (0..best_of)
+            .into_par_iter()
+            .map(|_| engine_arc.infer(None, &request.prompt, Some(&engine_config), None))
+            .collect();
+
+        let mut texts = Vec::with_capacity(results.len());
+        for result in results {
+            match result {
+                Ok(text) => texts.push(text),
+                Err(e) => {
+                    return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                        error: ErrorInfo {
+                            message: format!("Inference error: {}", e),
+                            error_type: "internal_error".to_string(),
+                            code: Some("inference_error".to_string()),
+                        },
+                    }));
+                }
             }
-            Err(e) => Ok(HttpResponse::InternalServerError().json(ErrorResponse {
-                error: ErrorInfo {
-                    message: format!("Inference error: {}", e),
-                    error_type: "internal_error".to_string(),
-                    code: Some("inference_error".to_string()),
-                },
-            })),
         }
+        texts.sort_by_key(|text| std::cmp::Reverse(text.len()));
+        texts.truncate(n as usize);
+
+        let completion_tokens: usize = texts.iter().map(|text| engine_arc.count_tokens(text)).sum();
+        let choices = texts
+            .into_iter()
+            .enumerate()
+            .map(|(index, text)| CompletionChoice {
+                text,
+                index: index as u32,
+                logprobs: None,
+                finish_reason: "stop".to_string(),
+            })
+            .collect();
+
+        Ok(HttpResponse::Ok().json(CompletionResponse {
+            id: Uuid::new_v4().to_string(),
+            object: "text_completion".to_string(),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            model: model_name,
+            choices,
+            usage: Usage::new(prompt_tokens, completion_tokens, start.elapsed()),
+        }))
     }
 }
 
@@ -362,6 +733,16 @@ pub async fn create_chat_completion(
 ) -> ActixResult<HttpResponse> {
     let stream_requested = request.stream.unwrap_or(false);
     let model_name = request.model.clone();
+    // chat completions 不支持 `best_of`（和 OpenAI 一致），直接把 `n` 当 best_of 传进去校验
+    let n = match validate_batch_size(
+        request.n,
+        request.n,
+        stream_requested,
+        crate::config::APP_CONFIG.max_client_batch_size,
+    ) {
+        Ok(n) => n,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(e)),
+    };
 
     // 转换消息格式
     let messages: Vec<Message> = request
@@ -369,12 +750,32 @@ pub async fn create_chat_completion(
         .iter()
         .map(|msg| Message {
             role: msg.role.clone(),
-            content: Some(msg.content.clone()),
-            tool_calls: None,
-            name: None,
+            content: Some(msg.content.clone().into()),
+            tool_calls: msg.tool_calls.as_ref().map(|calls| {
+                calls
+                    .iter()
+                    .map(|call| crate::template::ToolCall {
+                        id: Some(call.id.clone()),
+                        function: crate::template::Function {
+                            name: call.function.name.clone(),
+                            description: None,
+                            parameters: None,
+                            arguments: serde_json::from_str(&call.function.arguments).ok(),
+                        },
+                    })
+                    .collect()
+            }),
+            name: msg.name.clone(),
         })
         .collect();
 
+    // 将 OpenAI 风格的工具定义转换为模板所需的 Tool 结构，并按 tool_choice 过滤
+    let tools: Option<Vec<crate::template::Tool>> = request
+        .tools
+        .clone()
+        .map(|tools| tools.into_iter().map(ToolDefinition::into_template_tool).collect())
+        .and_then(|tools| apply_tool_choice(tools, request.tool_choice.as_ref()));
+
     // 转换参数到引擎配置
     let engine_config = EngineConfig {
         n_ctx: 4096,
@@ -383,6 +784,11 @@ pub async fn create_chat_completion(
         top_k: 40,
         top_p: request.top_p.unwrap_or(1.9),
         repeat_penalty: 1.1,
+        stop: request.stop.clone().unwrap_or_default(),
+        presence_penalty: request.presence_penalty.unwrap_or(0.0),
+        frequency_penalty: request.frequency_penalty.unwrap_or(0.0),
+        logit_bias: parse_logit_bias(request.logit_bias.as_ref()),
+        ..Default::default()
     };
 
     let engine_arc = match data.model_pool.get_model(&model_name).await {
@@ -398,6 +804,35 @@ pub async fn create_chat_completion(
         }
     };
 
+    // 检索增强：客户端带了 conversation_id 时，用最近一条用户消息去查这个
+    // 对话专属的向量库，把取回的历史段落拼进 system prompt；没有
+    // conversation_id、这个对话还没索引过任何内容，或者 embedding 失败时，
+    // 就什么都不做——不影响正常的非 RAG 推理路径。
+    let last_user_text = messages
+        .iter()
+        .rev()
+        .find(|m| m.role == "user")
+        .and_then(|m| m.content.as_ref())
+        .map(|c| c.as_text());
+    let mut rag_system = None;
+    if let (Some(conversation_id), Some(text)) = (&request.conversation_id, &last_user_text) {
+        if let Ok(mut vectors) = engine_arc.embed(std::slice::from_ref(text)) {
+            if !vectors.is_empty() {
+                let query = vectors.remove(0);
+                // 先查，再把这一条消息自己存进去——否则这一轮会先检索到自己。
+                let passages =
+                    crate::vectorstore::retrieve_context(conversation_id, &query, RAG_CONTEXT_TOP_K);
+                if !passages.is_empty() {
+                    rag_system = Some(format!(
+                        "Relevant context from earlier in this conversation:\n{}",
+                        passages.join("\n---\n")
+                    ));
+                }
+                crate::vectorstore::index_turn(conversation_id, query, text.clone());
+            }
+        }
+    }
+
     // 渲染聊天模板
     let prompt = match crate::template::render_template(
         &engine_arc.get_model_info(),
@@ -405,7 +840,10 @@ pub async fn create_chat_completion(
             .get_model_info()
             .template
             .unwrap_or(crate::template::get_default_template()),
-        &crate::template::TemplateData::new().with_messages(Some(messages)),
+        &crate::template::TemplateData::new()
+            .with_system(rag_system)
+            .with_messages(Some(messages))
+            .with_tools(tools),
     ) {
         Ok(prompt) => prompt,
         Err(e) => {
@@ -420,77 +858,221 @@ pub async fn create_chat_completion(
     };
 
     if stream_requested {
+        // chat 不支持 `best_of`，所以这里就是 `n` 条互不依赖的候选，各自带自己的
+        // `index` 往同一个 channel 里发 delta；最后一个收尾的候选发覆盖全部候选
+        // 的 usage chunk。
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<StreamChatCompletionResponse>();
-        let prompt_clone = prompt.clone();
         let model_name_clone = model_name.clone();
-        let engine_arc_clone = engine_arc.clone();
+        // 和下面的非流式分支一样：KV cache 会话是给单条线性对话复用的，n>1 时不传 conversation_id，
+        // 避免多条候选并发写同一份缓存。
+        let conversation_id = if n == 1 { request.conversation_id.clone() } else { None };
+        let prompt_tokens = engine_arc.count_tokens(&prompt);
+        let request_id = Uuid::new_v4().to_string();
+        let created = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let start = std::time::Instant::now();
+        let completion_tokens_total = Arc::new(AtomicUsize::new(0));
+        let pending = Arc::new(AtomicUsize::new(n as usize));
 
-        tokio::task::spawn_blocking(move || {
-            let tx_tokens = tx.clone();
-            let model_name_clone2 = model_name_clone.clone();
-            let request_id = Uuid::new_v4().to_string();
-            let request_id_clone = request_id.clone();
-            let created = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
-
-            // 执行推理并流式发送响应
-            let result = engine_arc_clone.infer(
-                &prompt_clone,
-                Some(&engine_config),
-                Some(Box::new(move |tok| {
-                    let response = StreamChatCompletionResponse {
-                        id: request_id.clone(),
-                        object: "chat.completion.chunk".to_string(),
-                        created,
-                        model: model_name_clone.clone(),
-                        choices: vec![StreamChatCompletionChoice {
-                            index: 0,
-                            delta: ChatMessage {
-                                role: "assistant".to_string(),
-                                content: tok.into(),
-                                name: None,
-                            },
-                            finish_reason: None,
-                        }],
-                    };
-                    let result = tx_tokens.send(response);
-                    if result.is_err() {
-                        println!("Error sending response: {:?}", result.err());
-                        return false;
-                    }
-                    true
-                })),
-            );
-            if result.is_err() {
-                println!("Error inferring: {:?}", result.err());
-                return;
-            }
-
-            // 发送结束信号
+        // OpenAI 的 chat completion 流第一个 chunk 只宣告 role、不带内容，
+        // 客户端据此初始化消息再累加后续 content delta；每条候选各发一条。
+        for index in 0..n {
             let _ = tx.send(StreamChatCompletionResponse {
-                id: request_id_clone,
+                id: request_id.clone(),
                 object: "chat.completion.chunk".to_string(),
                 created,
-                model: model_name_clone2,
+                model: model_name_clone.clone(),
                 choices: vec![StreamChatCompletionChoice {
-                    index: 0,
+                    index,
                     delta: ChatMessage {
                         role: "assistant".to_string(),
-                        content: String::new(),
+                        content: String::new().into(),
                         name: None,
+                        tool_calls: None,
+                        tool_call_id: None,
                     },
-                    finish_reason: Some("stop".to_string()),
+                    finish_reason: None,
                 }],
+                usage: None,
             });
-        });
+        }
+
+        for index in 0..n {
+            let tx_tokens = tx.clone();
+            let tx_done = tx.clone();
+            let prompt_clone = prompt.clone();
+            let model_name_clone = model_name_clone.clone();
+            let model_name_done = model_name_clone.clone();
+            let engine_arc_clone = engine_arc.clone();
+            let engine_config = engine_config.clone();
+            let conversation_id = conversation_id.clone();
+            let request_id_clone = request_id.clone();
+            let request_id_done = request_id.clone();
+            let completion_tokens_total = Arc::clone(&completion_tokens_total);
+            let pending = Arc::clone(&pending);
+
+            // 标签可能被 token 切开，所以用一个共享的 buffer 在回调之间累积状态；
+            // `infer` 结束后回调（以及它持有的 Arc 克隆）都已经被丢弃，剩下这一份
+            // 引用可以用来 flush 末尾内容、读出有没有命中过工具调用。
+            let tool_buffer = Arc::new(Mutex::new(ToolCallStreamBuffer::new()));
+            let saw_tool_call = Arc::new(AtomicBool::new(false));
+            let tool_buffer_cb = Arc::clone(&tool_buffer);
+            let saw_tool_call_cb = Arc::clone(&saw_tool_call);
+            // 流式回复也要和非流式分支一样把第一条候选的完整回复索引进向量库，
+            // 否则走 playground/arena 这类始终用流式接口的场景时 RAG 质量会
+            // 悄悄退化。按 token 累积到这里，等 `infer` 结束后再一次性 embed。
+            let assistant_text = Arc::new(Mutex::new(String::new()));
+            let assistant_text_cb = Arc::clone(&assistant_text);
+
+            tokio::task::spawn_blocking(move || {
+                // 执行推理并流式发送响应；带上 conversation_id 可以让本地引擎复用上一轮的 KV cache
+                let result = engine_arc_clone.infer(
+                    conversation_id.as_deref(),
+                    &prompt_clone,
+                    Some(&engine_config),
+                    Some(Box::new(move |tok| {
+                        for token in tool_buffer_cb.lock().unwrap().push(&tok) {
+                            let delta = match token {
+                                StreamToken::Content(text) => {
+                                    assistant_text_cb.lock().unwrap().push_str(&text);
+                                    ChatMessage {
+                                        role: "assistant".to_string(),
+                                        content: text.into(),
+                                        name: None,
+                                        tool_calls: None,
+                                        tool_call_id: None,
+                                    }
+                                }
+                                StreamToken::ToolCall(call) => {
+                                    saw_tool_call_cb.store(true, Ordering::SeqCst);
+                                    ChatMessage {
+                                        role: "assistant".to_string(),
+                                        content: String::new().into(),
+                                        name: None,
+                                        tool_calls: Some(vec![call]),
+                                        tool_call_id: None,
+                                    }
+                                }
+                            };
+                            let response = StreamChatCompletionResponse {
+                                id: request_id_clone.clone(),
+                                object: "chat.completion.chunk".to_string(),
+                                created,
+                                model: model_name_clone.clone(),
+                                choices: vec![StreamChatCompletionChoice {
+                                    index,
+                                    delta,
+                                    finish_reason: None,
+                                }],
+                                usage: None,
+                            };
+                            // 回调返回值是“要不要停止生成”，不是“发送是否成功”：只有
+                            // 接收端已经断开（客户端提前关闭了连接）才需要喊停。
+                            if tx_tokens.send(response).is_err() {
+                                println!("Error sending response");
+                                return true;
+                            }
+                        }
+                        false
+                    })),
+                );
+                if let Err(e) = &result {
+                    println!("Error inferring: {:?}", e);
+                }
+
+                let completion_tokens = engine_arc_clone.count_tokens(&result.unwrap_or_default());
+                completion_tokens_total.fetch_add(completion_tokens, Ordering::SeqCst);
+
+                // 生成已经结束，没有更多 token 会到来把悬空的 `<tool_call>` 标签补完，
+                // 把它按普通文本 flush 出去好过悄悄丢掉。
+                for token in tool_buffer.lock().unwrap().finish() {
+                    if let StreamToken::Content(text) = token {
+                        assistant_text.lock().unwrap().push_str(&text);
+                        let _ = tx_done.send(StreamChatCompletionResponse {
+                            id: request_id_done.clone(),
+                            object: "chat.completion.chunk".to_string(),
+                            created,
+                            model: model_name_done.clone(),
+                            choices: vec![StreamChatCompletionChoice {
+                                index,
+                                delta: ChatMessage {
+                                    role: "assistant".to_string(),
+                                    content: text.into(),
+                                    name: None,
+                                    tool_calls: None,
+                                    tool_call_id: None,
+                                },
+                                finish_reason: None,
+                            }],
+                            usage: None,
+                        });
+                    }
+                }
+
+                // 和非流式分支一样，只有 n == 1 时才有 conversation_id，也只索引
+                // 第一条（唯一一条）候选的回复。
+                if let Some(conversation_id) = &conversation_id {
+                    let full_text = assistant_text.lock().unwrap().clone();
+                    if !full_text.trim().is_empty() {
+                        if let Ok(mut vectors) = engine_arc_clone.embed(std::slice::from_ref(&full_text)) {
+                            if !vectors.is_empty() {
+                                crate::vectorstore::index_turn(conversation_id, vectors.remove(0), full_text);
+                            }
+                        }
+                    }
+                }
+
+                let finish_reason = if saw_tool_call.load(Ordering::SeqCst) {
+                    "tool_calls"
+                } else {
+                    "stop"
+                };
+                let _ = tx_done.send(StreamChatCompletionResponse {
+                    id: request_id_done.clone(),
+                    object: "chat.completion.chunk".to_string(),
+                    created,
+                    model: model_name_done.clone(),
+                    choices: vec![StreamChatCompletionChoice {
+                        index,
+                        delta: ChatMessage {
+                            role: "assistant".to_string(),
+                            content: String::new().into(),
+                            name: None,
+                            tool_calls: None,
+                            tool_call_id: None,
+                        },
+                        finish_reason: Some(finish_reason.to_string()),
+                    }],
+                    usage: None,
+                });
+
+                // 最后一个结束的候选负责发收尾的 usage chunk，覆盖整个请求的耗时
+                // 和全部候选加起来的 completion_tokens。
+                if pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+                    let _ = tx_done.send(StreamChatCompletionResponse {
+                        id: request_id_done,
+                        object: "chat.completion.chunk".to_string(),
+                        created,
+                        model: model_name_done,
+                        choices: vec![],
+                        usage: Some(Usage::new(
+                            prompt_tokens,
+                            completion_tokens_total.load(Ordering::SeqCst),
+                            start.elapsed(),
+                        )),
+                    });
+                }
+            });
+        }
 
         let stream = tokio_stream::wrappers::UnboundedReceiverStream::new(rx);
-        let stream = stream.map(|chunk| {
-            let json_str = serde_json::to_string(&chunk).unwrap();
-            Ok::<Bytes, actix_web::Error>(Bytes::from(format!("data: {}\n\n", json_str)))
-        });
+        let stream = stream
+            .map(|chunk| {
+                let json_str = serde_json::to_string(&chunk).unwrap();
+                Ok::<Bytes, actix_web::Error>(Bytes::from(format!("data: {}\n\n", json_str)))
+            })
+            .chain(tokio_stream::once(Ok(Bytes::from_static(
+                b"data: [DONE]\n\n",
+            ))));
 
         Ok(HttpResponse::Ok()
             .append_header(("Content-Type", "text/event-stream"))
@@ -498,49 +1080,377 @@ pub async fn create_chat_completion(
             .append_header(("Access-Control-Allow-Origin", "*"))
             .streaming(stream))
     } else {
-        match engine_arc.infer(&prompt, Some(&engine_config), None) {
-            Ok(text) => {
-                let response = ChatCompletionResponse {
-                    id: Uuid::new_v4().to_string(),
-                    object: "chat.completion".to_string(),
-                    created: SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-                    model: model_name,
-                    choices: vec![ChatCompletionChoice {
-                        index: 0,
-                        message: ChatMessage {
-                            role: "assistant".to_string(),
-                            content: text,
-                            name: None,
+        let start = std::time::Instant::now();
+        let prompt_tokens = engine_arc.count_tokens(&prompt);
+        // `n` 条候选共享同一个已加载的 `engine_arc`，用 rayon 并发生成。`conversation_id`
+        // 标记的 KV cache 是给单条线性对话复用的，n>1 时每条候选都写回去会相互踩，
+        // 所以只有 n == 1 时才把它传给引擎；否则都当一次性推理处理。
+        let conversation_id = if n == 1 {
+            request.conversation_id.clone()
+        } else {
+            None
+        };
+        let results: Vec<anyhow::Result<String>> = (0..n)
+            .into_par_iter()
+            .map(|_| engine_arc.infer(conversation_id.as_deref(), &prompt, Some(&engine_config), None))
+            .collect();
+
+        let mut texts = Vec::with_capacity(results.len());
+        for result in results {
+            match result {
+                Ok(text) => texts.push(text),
+                Err(e) => {
+                    return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                        error: ErrorInfo {
+                            message: format!("Inference error: {}", e),
+                            error_type: "internal_error".to_string(),
+                            code: Some("inference_error".to_string()),
                         },
-                        finish_reason: "stop".to_string(),
-                    }],
-                    usage: Usage {
-                        prompt_tokens: 0,
-                        completion_tokens: 0,
-                        total_tokens: 0,
-                    },
-                };
-                Ok(HttpResponse::Ok().json(response))
+                    }));
+                }
+            }
+        }
+
+        let completion_tokens: usize = texts.iter().map(|text| engine_arc.count_tokens(text)).sum();
+
+        // 把第一条候选的回复也索引进这个对话的向量库，供之后的轮次检索；
+        // `n` > 1 时后面几条候选是并列的备选项，不代表对话实际走向，不索引。
+        if let Some(conversation_id) = &conversation_id {
+            if let Some(first_text) = texts.first() {
+                if let Ok(mut vectors) = engine_arc.embed(std::slice::from_ref(first_text)) {
+                    if !vectors.is_empty() {
+                        crate::vectorstore::index_turn(
+                            conversation_id,
+                            vectors.remove(0),
+                            first_text.clone(),
+                        );
+                    }
+                }
             }
-            Err(e) => Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+        }
+        let choices = texts
+            .into_iter()
+            .enumerate()
+            .map(|(index, text)| {
+                let (leftover, parsed_tool_calls) = crate::template::parse_tool_calls(&text);
+                let (content, tool_calls, finish_reason) = if parsed_tool_calls.is_empty() {
+                    (text, None, "stop".to_string())
+                } else {
+                    let tool_calls = parsed_tool_calls
+                        .into_iter()
+                        .map(|call| ToolCallDto {
+                            id: call.id.unwrap_or_else(|| Uuid::new_v4().to_string()),
+                            call_type: "function".to_string(),
+                            function: ToolCallFunctionDto {
+                                name: call.function.name,
+                                arguments: call
+                                    .function
+                                    .arguments
+                                    .map(|v| v.to_string())
+                                    .unwrap_or_default(),
+                            },
+                        })
+                        .collect();
+                    // 工具调用前面那句话（如果有的话）不该因为命中了工具调用就消失。
+                    (leftover.unwrap_or_default(), Some(tool_calls), "tool_calls".to_string())
+                };
+                ChatCompletionChoice {
+                    index: index as u32,
+                    message: ChatMessage {
+                        role: "assistant".to_string(),
+                        content: content.into(),
+                        name: None,
+                        tool_calls,
+                        tool_call_id: None,
+                    },
+                    finish_reason,
+                }
+            })
+            .collect();
+
+        Ok(HttpResponse::Ok().json(ChatCompletionResponse {
+            id: Uuid::new_v4().to_string(),
+            object: "chat.completion".to_string(),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            model: model_name,
+            choices,
+            usage: Usage::new(prompt_tokens, completion_tokens, start.elapsed()),
+        }))
+    }
+}
+
+// OpenAI 兼容的 embeddings 请求/响应结构体
+#[derive(serde::Deserialize, Debug)]
+pub struct EmbeddingsRequest {
+    pub model: String,
+    pub input: EmbeddingsInput,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(untagged)]
+pub enum EmbeddingsInput {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl EmbeddingsInput {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            EmbeddingsInput::One(text) => vec![text],
+            EmbeddingsInput::Many(texts) => texts,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct EmbeddingsResponse {
+    pub object: String,
+    pub data: Vec<EmbeddingData>,
+    pub model: String,
+    pub usage: EmbeddingsUsage,
+}
+
+#[derive(serde::Serialize)]
+pub struct EmbeddingData {
+    pub object: String,
+    pub index: u32,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(serde::Serialize)]
+pub struct EmbeddingsUsage {
+    pub prompt_tokens: u32,
+    pub total_tokens: u32,
+}
+
+pub async fn create_embeddings(
+    request: web::Json<EmbeddingsRequest>,
+    data: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let model_name = request.model.clone();
+
+    let engine_arc = match data.model_pool.get_model(&model_name).await {
+        Ok(engine) => engine,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+                error: ErrorInfo {
+                    message: format!("Model not found: {}", e),
+                    error_type: "invalid_request_error".to_string(),
+                    code: Some("model_not_found".to_string()),
+                },
+            }));
+        }
+    };
+
+    let inputs = request.into_inner().input.into_vec();
+    let prompt_tokens: usize = inputs.iter().map(|text| engine_arc.count_tokens(text)).sum();
+
+    // 一次性把所有输入交给引擎，让实现（尤其是 llama.cpp 后端）只搭一次
+    // embedding 上下文，而不是每条输入都重新创建一次。
+    let embeddings = match engine_arc.embed(&inputs) {
+        Ok(embeddings) => embeddings,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
                 error: ErrorInfo {
-                    message: format!("Inference error: {}", e),
+                    message: format!("Embedding error: {}", e),
                     error_type: "internal_error".to_string(),
-                    code: Some("inference_error".to_string()),
+                    code: Some("embedding_error".to_string()),
                 },
-            })),
+            }));
         }
+    };
+    let data_items: Vec<EmbeddingData> = embeddings
+        .into_iter()
+        .enumerate()
+        .map(|(index, embedding)| EmbeddingData {
+            object: "embedding".to_string(),
+            index: index as u32,
+            embedding,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(EmbeddingsResponse {
+        object: "list".to_string(),
+        data: data_items,
+        model: model_name,
+        usage: EmbeddingsUsage {
+            prompt_tokens: prompt_tokens as u32,
+            total_tokens: prompt_tokens as u32,
+        },
+    }))
+}
+
+// 对话持久化：创建/列出/读取 `ConversationStore` 里的对话，
+// 形状参考 OpenAI 较新的 Conversations 资源（`POST/GET /v1/conversations`）。
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct CreateConversationRequest {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConversationResponse {
+    pub id: String,
+    pub object: String,
+    pub title: String,
+    pub system_prompt: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub messages: Option<Vec<ChatMessage>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConversationSummaryResponse {
+    pub id: String,
+    pub object: String,
+    pub title: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(serde::Serialize)]
+pub struct ListConversationsResponse {
+    pub object: String,
+    pub data: Vec<ConversationSummaryResponse>,
+}
+
+fn conversation_store_error(message: String) -> ErrorResponse {
+    ErrorResponse {
+        error: ErrorInfo {
+            message,
+            error_type: "internal_error".to_string(),
+            code: Some("conversation_store_error".to_string()),
+        },
+    }
+}
+
+pub async fn create_conversation(
+    request: web::Json<CreateConversationRequest>,
+) -> ActixResult<HttpResponse> {
+    let title = request
+        .title
+        .clone()
+        .unwrap_or_else(|| "New conversation".to_string());
+    let system_prompt = request.system_prompt.clone().unwrap_or_else(|| {
+        "You are a helpful, respectful and honest AI assistant.".to_string()
+    });
+
+    let store = crate::conversation_store::CONVERSATION_STORE.lock().unwrap();
+    match store.create_conversation(&title, &system_prompt) {
+        Ok(conversation) => Ok(HttpResponse::Ok().json(ConversationResponse {
+            id: conversation.id,
+            object: "conversation".to_string(),
+            title: conversation.title,
+            system_prompt: conversation.system_prompt,
+            created_at: conversation.created_at,
+            updated_at: conversation.updated_at,
+            messages: None,
+        })),
+        Err(e) => Ok(HttpResponse::InternalServerError()
+            .json(conversation_store_error(format!(
+                "Failed to create conversation: {}",
+                e
+            )))),
+    }
+}
+
+pub async fn list_conversations() -> ActixResult<HttpResponse> {
+    let store = crate::conversation_store::CONVERSATION_STORE.lock().unwrap();
+    match store.list_conversations() {
+        Ok(conversations) => Ok(HttpResponse::Ok().json(ListConversationsResponse {
+            object: "list".to_string(),
+            data: conversations
+                .into_iter()
+                .map(|conversation| ConversationSummaryResponse {
+                    id: conversation.id,
+                    object: "conversation".to_string(),
+                    title: conversation.title,
+                    created_at: conversation.created_at,
+                    updated_at: conversation.updated_at,
+                })
+                .collect(),
+        })),
+        Err(e) => Ok(HttpResponse::InternalServerError()
+            .json(conversation_store_error(format!(
+                "Failed to list conversations: {}",
+                e
+            )))),
+    }
+}
+
+pub async fn get_conversation(path: web::Path<String>) -> ActixResult<HttpResponse> {
+    let id = path.into_inner();
+    let store = crate::conversation_store::CONVERSATION_STORE.lock().unwrap();
+    match store.get_conversation(&id) {
+        Ok(Some(conversation)) => Ok(HttpResponse::Ok().json(ConversationResponse {
+            id: conversation.id,
+            object: "conversation".to_string(),
+            title: conversation.title,
+            system_prompt: conversation.system_prompt,
+            created_at: conversation.created_at,
+            updated_at: conversation.updated_at,
+            messages: Some(
+                conversation
+                    .messages
+                    .into_iter()
+                    .map(|message| ChatMessage {
+                        role: message.role,
+                        content: message.content.unwrap_or_default(),
+                        name: message.name,
+                        tool_calls: message.tool_calls.map(|calls| {
+                            calls
+                                .into_iter()
+                                .map(|call| ToolCallDto {
+                                    id: call.id.unwrap_or_default(),
+                                    call_type: "function".to_string(),
+                                    function: ToolCallFunctionDto {
+                                        name: call.function.name,
+                                        arguments: call
+                                            .function
+                                            .arguments
+                                            .map(|v| v.to_string())
+                                            .unwrap_or_default(),
+                                    },
+                                })
+                                .collect()
+                        }),
+                        tool_call_id: None,
+                    })
+                    .collect(),
+            ),
+        })),
+        Ok(None) => Ok(HttpResponse::NotFound().json(ErrorResponse {
+            error: ErrorInfo {
+                message: format!("Conversation '{}' not found", id),
+                error_type: "invalid_request_error".to_string(),
+                code: Some("conversation_not_found".to_string()),
+            },
+        })),
+        Err(e) => Ok(HttpResponse::InternalServerError()
+            .json(conversation_store_error(format!(
+                "Failed to load conversation: {}",
+                e
+            )))),
     }
 }
 
 // 健康检查端点
 pub async fn health_check() -> ActixResult<HttpResponse> {
+    #[cfg(feature = "engine-hf")]
+    let daemon = json!(crate::engine::hf::PYTHON_BACKEND.health());
+    #[cfg(not(feature = "engine-hf"))]
+    let daemon = Value::Null;
+
     Ok(HttpResponse::Ok().json(json!({
         "status": "ok",
-        "version": env!("CARGO_PKG_VERSION")
+        "version": env!("CARGO_PKG_VERSION"),
+        "daemon": daemon,
     })))
 }
 
@@ -551,6 +1461,14 @@ pub fn openai_config(cfg: &mut web::ServiceConfig) {
             .route("/models", web::get().to(list_models))
             .route("/completions", web::post().to(create_completion))
             .route("/chat/completions", web::post().to(create_chat_completion))
+            .route("/embeddings", web::post().to(create_embeddings))
+            .route("/conversations", web::post().to(create_conversation))
+            .route("/conversations", web::get().to(list_conversations))
+            .route("/conversations/{id}", web::get().to(get_conversation))
             .route("/health", web::get().to(health_check)),
     );
+    // 零配置的内置 UI：打包进二进制的静态页面，打开服务地址就能直接聊天，
+    // 不需要额外起一个前端项目或配置跨域。
+    cfg.route("/", web::get().to(super::ui::playground))
+        .route("/arena", web::get().to(super::ui::arena));
 }
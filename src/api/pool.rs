@@ -1,33 +1,46 @@
-use std::{collections::HashMap, error::Error, sync::Arc};
+use std::{collections::HashMap, collections::VecDeque, error::Error, sync::Arc};
 use tokio::sync::Mutex; // 使用 tokio 的 Mutex
 
-use crate::{
-    discover::MODEL_DISCOVERER,
-    engine::{EngineConfig, InferenceEngine}, // 确保 InferenceEngine trait 在作用域内
-};
+use super::backend::{BackendRegistry, TransformBackend};
+use crate::{config::APP_CONFIG, discover::MODEL_DISCOVERER};
 
 pub struct ModelPool {
-    models: Mutex<HashMap<String, Arc<dyn InferenceEngine + Send>>>,
+    models: Mutex<HashMap<String, Arc<dyn TransformBackend>>>,
+    /// 按最近使用时间排序的模型名，队尾是最近使用的，用于 `max_loaded_models` 的 LRU 淘汰。
+    lru: Mutex<VecDeque<String>>,
+    backends: BackendRegistry,
 }
 
 impl ModelPool {
     pub fn new() -> Self {
         ModelPool {
             models: Mutex::new(HashMap::new()),
+            lru: Mutex::new(VecDeque::new()),
+            backends: BackendRegistry::new(),
         }
     }
 
+    /// 把 `model_name` 标记为最近使用，供 LRU 淘汰判断。
+    async fn touch(&self, model_name: &str) {
+        let mut lru_guard = self.lru.lock().await;
+        lru_guard.retain(|name| name != model_name);
+        lru_guard.push_back(model_name.to_string());
+    }
+
     pub async fn get_model(
         &self,
         model_name: &str,
-    ) -> Result<Arc<dyn InferenceEngine + Send>, Box<dyn Error>> {
+    ) -> Result<Arc<dyn TransformBackend>, Box<dyn Error>> {
         // 1. 尝试从池中获取模型，如果存在则直接返回
         {
             let models_guard = self.models.lock().await; // 异步锁
             if let Some(engine_arc) = models_guard.get(model_name) {
                 println!("[ModelPool] Model '{}' found in pool.", model_name);
+                let engine_arc = Arc::clone(engine_arc);
+                drop(models_guard);
+                self.touch(model_name).await;
                 // 返回克隆的 Arc<...>
-                return Ok(Arc::clone(engine_arc));
+                return Ok(engine_arc);
             }
         } // `models_guard` 在这里超出作用域，释放了锁。
 
@@ -50,18 +63,19 @@ impl ModelPool {
                 })?
         };
 
-        // 定义用于加载模型的默认 EngineConfig。
-        let engine_config = EngineConfig {
-            n_ctx: 4096,
-            n_len: None, // 假设这是一个合理的默认值，或者根据实际情况调整
-            temperature: 0.8,
-            top_k: 40,
-            top_p: 0.9,
-            repeat_penalty: 1.1,
-        };
+        // 通过集中式配置解析出该模型应该使用的 EngineConfig（全局默认值 +
+        // 按模型名的覆盖项），而不是在这里硬编码一份。API 场景下的最大生成
+        // token 数只在模型自己没有显式配置 n_len 时才作为兜底值生效。
+        let mut engine_config = APP_CONFIG.resolve(model_name);
+        if engine_config.n_len.is_none() {
+            engine_config.n_len = APP_CONFIG.max_completion_tokens.api;
+        }
 
-        // 加载 LlamaEngine。这是一个可能耗时的操作。
-        let concrete_engine = crate::engine::llama_cpp::LlamaEngine::new(&engine_config, &model)
+        // 根据模型声明的类型，从后端注册表里选出对应的后端并构造它。
+        // 这是一个可能耗时的操作（例如本地后端要加载模型权重）。
+        let new_engine_arc = self
+            .backends
+            .build(&engine_config, &model)
             .map_err(|e| -> Box<dyn Error> {
                 Box::new(std::io::Error::new(
                     std::io::ErrorKind::Other,
@@ -73,17 +87,36 @@ impl ModelPool {
             llama_cpp_2::LogOptions::default().with_logs_enabled(true),
         );
 
-        // 将加载的引擎封装在 tokio::sync::Mutex 中，然后再封装在 Arc 中
-        let new_engine_arc: Arc<dyn InferenceEngine + Send> = Arc::new(concrete_engine);
-
         // 3. 将新加载的模型添加到池中
         let mut models_guard = self.models.lock().await; // 重新获取锁以修改 HashMap
         models_guard.insert(model_name.to_string(), Arc::clone(&new_engine_arc));
+        drop(models_guard);
+        self.touch(model_name).await;
 
         println!(
             "[ModelPool] Model '{}' loaded and added to pool.",
             model_name
         );
+
+        // 4. 按 `max_loaded_models` 淘汰最久未用的模型，直到不超过上限为止
+        if let Some(max_loaded) = APP_CONFIG.max_loaded_models {
+            loop {
+                let victim = {
+                    let models_guard = self.models.lock().await;
+                    if models_guard.len() <= max_loaded {
+                        break;
+                    }
+                    let lru_guard = self.lru.lock().await;
+                    lru_guard
+                        .iter()
+                        .find(|name| name.as_str() != model_name)
+                        .cloned()
+                };
+                let Some(victim) = victim else { break };
+                self.unload_model(&victim).await;
+            }
+        }
+
         Ok(new_engine_arc)
     }
 
@@ -95,5 +128,7 @@ impl ModelPool {
                 println!("[ModelPool] Model '{}' unloaded from pool.", model_name);
             }
         }
+        let mut lru_guard = self.lru.lock().await;
+        lru_guard.retain(|name| name != model_name);
     }
 }
@@ -4,9 +4,20 @@ use crate::{discover::MODEL_DISCOVERER, engine::EngineConfig};
 use actix_web::web::Bytes;
 use actix_web::{HttpResponse, Result as ActixResult, web};
 use serde_json::json;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio_stream::StreamExt;
 
+/// 当客户端断开连接、流被提前丢弃时，标记取消，避免 drop guard 之外的分支误以为请求仍然存活。
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
 #[derive(serde::Deserialize)]
 struct InferArgs {
     pub model: String,               // 模型名称
@@ -67,16 +78,21 @@ async fn common_inference(
         let prompt_clone = prompt.clone();
         let model_name_clone = model_name.clone();
         let engine_mutex_arc_clone = engine_mutex_arc.clone();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let cancelled_clone = Arc::clone(&cancelled);
 
         tokio::spawn(async move {
             let tx_tokens = tx.clone();
             let model_name_clone2 = model_name_clone.clone();
 
-            // 执行推理并流式发送响应
+            // 执行推理并流式发送响应，客户端断开时尽快中止，避免在连接关闭后继续消耗算力
             let _ = engine_mutex_arc_clone.lock().await.infer(
                 &prompt_clone,
                 Some(Box::new(move |tok| {
-                    let _ = tx_tokens.send(StreamChunk {
+                    if cancelled_clone.load(Ordering::Relaxed) {
+                        return true;
+                    }
+                    let result = tx_tokens.send(StreamChunk {
                         id: "".into(),
                         content: tok.into(),
                         created: SystemTime::now()
@@ -87,6 +103,11 @@ async fn common_inference(
                         finished: false,
                         finish_reason: None,
                     });
+                    if result.is_err() {
+                        cancelled_clone.store(true, Ordering::Relaxed);
+                        return true;
+                    }
+                    false
                 })),
             );
 
@@ -105,7 +126,9 @@ async fn common_inference(
         });
 
         let stream = tokio_stream::wrappers::UnboundedReceiverStream::new(rx);
-        let stream = stream.map(|chunk| {
+        let _cancel_guard = CancelOnDrop(cancelled);
+        let stream = stream.map(move |chunk| {
+            let _keep_alive = &_cancel_guard;
             let json_str = serde_json::to_string(&chunk).unwrap();
             Ok::<Bytes, actix_web::Error>(Bytes::from(format!("data: {}\n\n", json_str)))
         });
@@ -184,6 +207,7 @@ pub async fn infer(
         top_k: args.top_k.unwrap_or(40),
         top_p: args.top_p.unwrap_or(0.95),
         repeat_penalty: args.repeat_penalty.unwrap_or(1.1),
+        ..Default::default()
     };
 
     common_inference(model_name, prompt, data, stream_requested, engine_config).await
@@ -204,6 +228,7 @@ pub async fn chat(
         top_k: args.top_k.unwrap_or(40),
         top_p: args.top_p.unwrap_or(0.95),
         repeat_penalty: args.repeat_penalty.unwrap_or(1.1),
+        ..Default::default()
     };
 
     let prompt = crate::template::render_chatml_template(&crate::template::PromptData {
@@ -217,6 +242,26 @@ pub async fn chat(
     common_inference(model_name, prompt, data, stream_requested, engine_config).await
 }
 
+#[derive(serde::Deserialize)]
+struct CancelArgs {
+    pub req_id: String, // 要中止的那次 infer_with_callback 请求的 req_id
+}
+
+/// 显式中止一次仍在生成中的请求。目前只有走 Python 守护进程的 `engine-hf`
+/// 后端按 `req_id` 寻址，所以这个路由只在该 feature 下注册；本地 llama.cpp
+/// 后端的中止走的是 HTTP 连接断开时 `CancelOnDrop` 触发的回调返回值，不需要
+/// 客户端显式调用这个端点。
+#[cfg(feature = "engine-hf")]
+#[actix_web::post("/rllama/cancel")]
+pub async fn cancel(args: web::Query<CancelArgs>) -> ActixResult<HttpResponse> {
+    match crate::engine::hf::PYTHON_BACKEND.cancel(&args.req_id) {
+        Ok(()) => Ok(HttpResponse::Ok().json(json!({"message": "Cancellation requested."}))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": e.to_string()
+        }))),
+    }
+}
+
 #[actix_web::get("/rllama/discover")]
 pub async fn discover() -> ActixResult<HttpResponse> {
     let models = match MODEL_DISCOVERER.lock() {
@@ -237,4 +282,6 @@ pub fn rllama_config(cfg: &mut web::ServiceConfig) {
         .service(infer)
         .service(chat)
         .service(discover);
+    #[cfg(feature = "engine-hf")]
+    cfg.service(cancel);
 }
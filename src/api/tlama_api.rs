@@ -5,9 +5,19 @@ use actix_web::web::Bytes;
 use actix_web::{HttpResponse, Result as ActixResult, web};
 use serde_json::json;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio_stream::StreamExt;
 
+/// 当客户端断开连接、流被提前丢弃时，标记取消，避免 drop guard 之外的分支误以为请求仍然存活。
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
 #[derive(serde::Deserialize)]
 struct InferArgs {
     pub model: String,               // 模型名称
@@ -66,17 +76,23 @@ async fn common_inference(
         let prompt_clone = prompt.clone();
         let model_name_clone = model_name.clone();
         let engine_arc_clone = Arc::clone(&engine_arc);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let cancelled_clone = Arc::clone(&cancelled);
 
         tokio::task::spawn_blocking(move || {
             let id = uuid::Uuid::new_v4().to_string();
             let id_clone2 = id.clone();
             let tx_tokens = tx.clone();
             let model_name_clone2 = model_name_clone.clone();
-            // 执行推理并流式发送响应
+            // 执行推理并流式发送响应，客户端断开时尽快中止，避免在连接关闭后继续消耗算力
             let _ = engine_arc_clone.infer(
+                None,
                 &prompt_clone,
                 Some(&engine_config),
                 Some(Box::new(move |tok| {
+                    if cancelled_clone.load(Ordering::Relaxed) {
+                        return true;
+                    }
                     let id_clone = id.clone();
                     let result = tx_tokens.send(StreamChunk {
                         id: id_clone.into(),
@@ -91,12 +107,14 @@ async fn common_inference(
                     });
                     if let Err(e) = result {
                         eprintln!("Error sending chunk: {}", e);
-                        return;
+                        cancelled_clone.store(true, Ordering::Relaxed);
+                        return true;
                     }
+                    false
                 })),
             );
 
-            // 发送结束信号
+            // 发送结束信号（若客户端已断开则跳过）
             let _ = tx.send(StreamChunk {
                 id: id_clone2.into(),
                 content: "".into(),
@@ -111,7 +129,9 @@ async fn common_inference(
         });
 
         let stream = tokio_stream::wrappers::UnboundedReceiverStream::new(rx);
-        let stream = stream.map(|chunk| {
+        let _cancel_guard = CancelOnDrop(cancelled);
+        let stream = stream.map(move |chunk| {
+            let _keep_alive = &_cancel_guard;
             let json_str = serde_json::to_string(&chunk).unwrap();
             Ok::<Bytes, actix_web::Error>(Bytes::from(format!("data: {}\n\n", json_str)))
         });
@@ -122,7 +142,7 @@ async fn common_inference(
             .streaming(stream))
     } else {
         // 非流式推理
-        match engine_arc.infer(&prompt, None, None) {
+        match engine_arc.infer(None, &prompt, None, None) {
             Ok(text) => Ok(HttpResponse::Ok().json(json!({ "response": text }))),
             Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
                 "error": e.to_string()
@@ -189,6 +209,7 @@ pub async fn infer(
         top_k: args.top_k.unwrap_or(40),
         top_p: args.top_p.unwrap_or(0.95),
         repeat_penalty: args.repeat_penalty.unwrap_or(1.1),
+        ..Default::default()
     };
 
     common_inference(model_name, prompt, data, stream_requested, engine_config).await
@@ -209,6 +230,7 @@ pub async fn chat(
         top_k: args.top_k.unwrap_or(40),
         top_p: args.top_p.unwrap_or(0.95),
         repeat_penalty: args.repeat_penalty.unwrap_or(1.1),
+        ..Default::default()
     };
 
     let prompt = crate::template::render_chatml_template(
@@ -0,0 +1,20 @@
+//! 内置的零配置 Web UI：一个能直接对着 `/v1/chat/completions` 聊天的 playground，
+//! 和一个能把同一个 prompt 并发发给两个模型、并排看输出的 arena。两边都是纯静态
+//! HTML + 原生 JS，打包进二进制里，不需要额外起一个前端项目就能试用
+//! `MODEL_DISCOVERER` 发现出来的本地 GGUF / Transformers 模型。
+use actix_web::{HttpResponse, Result as ActixResult};
+
+const PLAYGROUND_HTML: &str = include_str!("assets/playground.html");
+const ARENA_HTML: &str = include_str!("assets/arena.html");
+
+pub async fn playground() -> ActixResult<HttpResponse> {
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(PLAYGROUND_HTML))
+}
+
+pub async fn arena() -> ActixResult<HttpResponse> {
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(ARENA_HTML))
+}
@@ -9,10 +9,10 @@ use std::thread;
 use std::time::Duration;
 
 use crate::discover::Model;
-use crate::{
-    engine::{EngineConfig, InferenceEngine},
-    template::*,
-};
+use crate::engine::agent::{Agent, AgentOutcome};
+use crate::tools::ToolRegistry;
+use crate::{engine::InferenceEngine, template::*};
+use serde_json::Value;
 
 struct Spinner {
     handle: Option<thread::JoinHandle<()>>,
@@ -66,18 +66,90 @@ impl Spinner {
 }
 // --- 模块结束 ---
 
+/// 一轮对话内允许的最大工具调用步数，防止模型反复调用工具陷入死循环
+const MAX_TOOL_STEPS: usize = 5;
+
+/// 检索增强时拼进 system prompt 的历史段落条数
+const RAG_CONTEXT_TOP_K: usize = 3;
+
 struct ChatSession {
     engine: Box<dyn InferenceEngine>,
     data: Vec<Message>,
     system_prompt: String,
+    /// 用 `Arc` 包装是因为 `chat()` 每轮都要把它交给一个独立构造的
+    /// `engine::agent::Agent`，后者的工具 handler 闭包需要各自持有一份
+    /// 能跨线程池共享的引用，而不是要求 `ToolRegistry` 自己实现 `Clone`。
+    tools: Arc<ToolRegistry>,
+    /// 当前对话在 `ConversationStore` 里的 id，每轮对话结束后按这个 id flush 消息，
+    /// 也是 `vectorstore` 里按对话隔离检索上下文的 key。
+    conversation_id: String,
+}
+
+/// 把 `tools` 里注册的每个工具包成一个 `engine::agent::AgentToolHandler`，
+/// 交给 `Agent::builder()` 组装出一个可以跑完整多步工具调用循环的执行器。
+/// handler 直接委托给 `ToolRegistry::call`，所以参数校验和 panic 隔离都是
+/// 复用现成的，这里只需要把错误类型从 `String` 转成 `Box<dyn Error>`。
+fn build_agent(tools: Arc<ToolRegistry>, max_steps: usize) -> Agent {
+    let mut builder = Agent::builder().max_steps(max_steps);
+    if let Some(defs) = tools.tool_defs() {
+        for def in defs {
+            let name = def.function.name;
+            let tools = Arc::clone(&tools);
+            let handler_name = name.clone();
+            builder = builder.tool(&name, move |arguments| {
+                tools
+                    .call(&handler_name, arguments)
+                    .map_err(|e| -> Box<dyn std::error::Error> { e.into() })
+            });
+        }
+    }
+    builder.build()
 }
 
 impl ChatSession {
     fn new(engine: Box<dyn InferenceEngine>) -> Self {
+        let system_prompt = "You are a helpful, respectful and honest AI assistant.".to_string();
+        let conversation_id = crate::conversation_store::CONVERSATION_STORE
+            .lock()
+            .unwrap()
+            .create_conversation("New conversation", &system_prompt)
+            .map(|conversation| conversation.id)
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "{} {}",
+                    "Failed to create a conversation store entry:".red(),
+                    e
+                );
+                uuid::Uuid::new_v4().to_string()
+            });
         Self {
             engine,
             data: vec![],
-            system_prompt: "You are a helpful, respectful and honest AI assistant.".to_string(),
+            system_prompt,
+            tools: Arc::new(ToolRegistry::with_builtins()),
+            conversation_id,
+        }
+    }
+
+    /// 用已保存的对话替换当前会话状态，用于启动时恢复最近一次对话。向量库只
+    /// 活在进程内存里，所以把恢复的历史消息重新 embed 一遍，补回检索索引。
+    fn resume(&mut self, conversation: crate::conversation_store::Conversation) {
+        self.conversation_id = conversation.id;
+        self.system_prompt = conversation.system_prompt;
+        self.data = conversation.messages;
+        for message in &self.data {
+            self.index_for_retrieval(message);
+        }
+    }
+
+    /// 把一条消息 flush 进 `ConversationStore`，崩溃或意外退出时不会丢失已完成的轮次
+    fn persist(&self, message: &Message) {
+        if let Err(e) = crate::conversation_store::CONVERSATION_STORE
+            .lock()
+            .unwrap()
+            .append_message(&self.conversation_id, message)
+        {
+            eprintln!("{} {}", "Failed to save message:".red(), e);
         }
     }
 
@@ -109,6 +181,16 @@ impl ChatSession {
                 );
                 println!("  {:<15} {}", ".clear", "Clear the conversation history.");
                 println!("  {:<15} {}", ".history", "Show the conversation history.");
+                println!(
+                    "  {:<15} {}",
+                    ".save [title]", "Save the conversation, optionally renaming it."
+                );
+                println!(
+                    "  {:<15} {}",
+                    ".load <id>", "Load a previously saved conversation."
+                );
+                println!("  {:<15} {}", ".list", "List saved conversations.");
+                println!("  {:<15} {}", ".new", "Start a new conversation.");
                 println!("  {:<15} {}", ".exit", "Exit the chat session.");
             }
             ".system" => {
@@ -142,8 +224,77 @@ impl ChatSession {
                         } else {
                             "AI".cyan()
                         };
-                        println!("{}: {}", prefix, msg.content.as_deref().unwrap_or(""));
+                        let content = msg.content.as_ref().map(|c| c.as_text()).unwrap_or_default();
+                        println!("{}: {}", prefix, content);
+                    }
+                }
+            }
+            ".save" => {
+                let store = crate::conversation_store::CONVERSATION_STORE.lock().unwrap();
+                if let Some(title) = parts.get(1) {
+                    if let Err(e) = store.rename_conversation(&self.conversation_id, title) {
+                        println!("{} {}", "Failed to save conversation:".red(), e);
+                        return Ok(true);
+                    }
+                }
+                println!(
+                    "{} {}",
+                    "Conversation saved:".green(),
+                    self.conversation_id
+                );
+            }
+            ".load" => {
+                let Some(id) = parts.get(1) else {
+                    println!("{}", "Usage: .load <id>".yellow());
+                    return Ok(true);
+                };
+                let store = crate::conversation_store::CONVERSATION_STORE.lock().unwrap();
+                match store.get_conversation(id) {
+                    Ok(Some(conversation)) => {
+                        let message_count = conversation.messages.len();
+                        self.resume(conversation);
+                        println!(
+                            "{} {} ({} messages)",
+                            "Loaded conversation:".green(),
+                            self.conversation_id,
+                            message_count
+                        );
                     }
+                    Ok(None) => println!("{} {}", "No such conversation:".red(), id),
+                    Err(e) => println!("{} {}", "Failed to load conversation:".red(), e),
+                }
+            }
+            ".list" => {
+                let store = crate::conversation_store::CONVERSATION_STORE.lock().unwrap();
+                match store.list_conversations() {
+                    Ok(conversations) => {
+                        if conversations.is_empty() {
+                            println!("  (No saved conversations yet)");
+                        } else {
+                            for conversation in conversations {
+                                println!("  {} {}", conversation.id.dimmed(), conversation.title);
+                            }
+                        }
+                    }
+                    Err(e) => println!("{} {}", "Failed to list conversations:".red(), e),
+                }
+            }
+            ".new" => {
+                let created = crate::conversation_store::CONVERSATION_STORE
+                    .lock()
+                    .unwrap()
+                    .create_conversation("New conversation", &self.system_prompt);
+                match created {
+                    Ok(conversation) => {
+                        self.conversation_id = conversation.id;
+                        self.data.clear();
+                        println!(
+                            "{} {}",
+                            "Started new conversation:".green(),
+                            self.conversation_id
+                        );
+                    }
+                    Err(e) => println!("{} {}", "Failed to start new conversation:".red(), e),
                 }
             }
             _ => {
@@ -201,17 +352,125 @@ impl ChatSession {
         Ok(())
     }
 
+    /// 把一条消息推入内存历史，并立刻 flush 到 `ConversationStore`
+    fn push_message(&mut self, message: Message) {
+        self.persist(&message);
+        self.index_for_retrieval(&message);
+        self.data.push(message);
+    }
+
+    /// 把消息的文本内容 embed 后存进这个对话专属的向量库里，供后续轮次
+    /// 检索增强；embedding 失败（比如后端不支持）只打印一条提示，不影响对话。
+    fn index_for_retrieval(&self, message: &Message) {
+        let Some(content) = message.content.as_ref() else {
+            return;
+        };
+        let text = content.as_text();
+        if text.trim().is_empty() {
+            return;
+        }
+        match self.engine.embed(&[text.clone()]) {
+            Ok(mut vectors) if !vectors.is_empty() => {
+                crate::vectorstore::index_turn(&self.conversation_id, vectors.remove(0), text);
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("{} {}", "Failed to index message for retrieval:".dimmed(), e),
+        }
+    }
+
     fn chat(&mut self, user_input: &str) -> Result<(), Box<dyn std::error::Error>> {
-        self.data.push(Message {
+        self.push_message(Message {
             role: "user".to_string(),
-            content: Some(user_input.to_string()),
+            content: Some(user_input.to_string().into()),
             tool_calls: None,
             name: None,
         });
 
+        // 实际的多步工具调用循环委托给可复用的 `engine::agent::Agent`；这里只
+        // 负责 CLI 特有的部分：喂一份 `self.data` 的本地副本给它推理（循环跑到
+        // 一半时 `self.data` 本身还没更新，所以 `infer_once` 读的是这份传入的
+        // history），跑完之后把它新追加的消息依次 `push_message` 回去（补上
+        // 持久化和检索建索引），并把工具调用打印出来，保持之前逐步执行时的
+        // 输出观感。
+        let agent = build_agent(Arc::clone(&self.tools), MAX_TOOL_STEPS);
+        let mut history = self.data.clone();
+        let base_len = history.len();
+        let outcome = agent.run(&mut history, |messages| self.infer_once(messages))?;
+
+        for message in history.into_iter().skip(base_len) {
+            if let Some(tool_calls) = &message.tool_calls {
+                for call in tool_calls {
+                    println!(
+                        "{} {}({})",
+                        "[tool]".magenta(),
+                        call.function.name,
+                        call.function.arguments.clone().unwrap_or(Value::Null)
+                    );
+                }
+            }
+            self.push_message(message);
+        }
+
+        if let AgentOutcome::StepLimitReached = outcome {
+            // 把"放弃"也当成一条正式的 assistant 消息 push 出去：否则历史记录会停在
+            // 最后一条 tool 消息上，`.save`/`.load` 恢复的对话会在下一轮立刻重新
+            // 触发同一轮工具调用，再次撞到步数上限。
+            let giveup_message = "Reached the tool-call step limit without a final answer.";
+            println!("{}", giveup_message.yellow());
+            self.push_message(Message {
+                role: "assistant".to_string(),
+                content: Some(giveup_message.to_string().into()),
+                tool_calls: None,
+                name: None,
+            });
+        }
+        Ok(())
+    }
+
+    /// 检索增强：embed 最近一条用户消息，取回这个对话里最相关的历史段落，
+    /// 拼在 system prompt 后面。还没有可检索的内容，或者 embedding 本身失败
+    /// 时，原样返回 `self.system_prompt`，不影响正常推理。
+    fn system_prompt_with_context(&self, history: &[Message]) -> String {
+        let Some(last_user_text) = history
+            .iter()
+            .rev()
+            .find(|m| m.role == "user")
+            .and_then(|m| m.content.as_ref())
+            .map(|c| c.as_text())
+        else {
+            return self.system_prompt.clone();
+        };
+
+        let query = match self.engine.embed(&[last_user_text]) {
+            Ok(mut vectors) if !vectors.is_empty() => vectors.remove(0),
+            _ => return self.system_prompt.clone(),
+        };
+
+        let passages = crate::vectorstore::retrieve_context(
+            &self.conversation_id,
+            &query,
+            RAG_CONTEXT_TOP_K,
+        );
+        if passages.is_empty() {
+            return self.system_prompt.clone();
+        }
+
+        format!(
+            "{}\n\nRelevant context from earlier in this conversation:\n{}",
+            self.system_prompt,
+            passages.join("\n---\n")
+        )
+    }
+
+    /// 渲染 `history` 并调用一次模型推理，带启动/停止 spinner 的动画。`history`
+    /// 由调用方传入而不是直接读 `self.data`：多步工具调用循环跑到一半时，最新
+    /// 的 assistant/tool 消息还只存在于 `Agent::run` 的本地历史副本里，要到整
+    /// 轮结束后才会统一 `push_message` 回 `self.data`。
+    fn infer_once(&mut self, history: &[Message]) -> Result<String, Box<dyn std::error::Error>> {
         let prompt_data = TemplateData::new()
-            .with_system(Some(self.system_prompt.clone()))
-            .with_messages(Some(self.data.clone()));
+            .with_system(Some(self.system_prompt_with_context(history)))
+            .with_tools(self.tools.tool_defs())
+            .with_messages(Some(history.to_vec()));
 
         let prompt = render_chatml_template(&prompt_data)?;
 
@@ -222,6 +481,7 @@ impl ChatSession {
         let mut first_token = true;
 
         let result = self.engine.infer(
+            Some(&self.conversation_id),
             &prompt,
             None,
             crate::def_callback!(|token| {
@@ -249,13 +509,7 @@ impl ChatSession {
 
         println!(); // 在 AI 回复结束后换行
 
-        self.data.push(Message {
-            role: "assistant".to_string(),
-            content: Some(result?),
-            tool_calls: None,
-            name: None,
-        });
-        Ok(())
+        Ok(result?)
     }
 }
 
@@ -271,14 +525,12 @@ pub fn chat_session(args: crate::cli::ChatArgs) -> Result<(), Box<dyn std::error
             .find_model(&args.model)?;
     }
 
-    let engine_config = EngineConfig {
-        n_ctx: 2048,
-        n_len: None,
-        temperature: 0.8,
-        top_k: 40,
-        top_p: 0.9,
-        repeat_penalty: 1.1,
-    };
+    // 通过集中式配置解析出该模型应该使用的 EngineConfig（全局默认值 + 按模型名
+    // 的覆盖项），交互式 chat 场景下的最大生成 token 数和 API 分开配置。
+    let mut engine_config = crate::config::APP_CONFIG.resolve(&model_path.name);
+    if engine_config.n_len.is_none() {
+        engine_config.n_len = crate::config::APP_CONFIG.max_completion_tokens.chat;
+    }
 
     // --- 修改: 在加载模型时使用动画 ---
     // 1. 启动 spinner
@@ -303,6 +555,31 @@ pub fn chat_session(args: crate::cli::ChatArgs) -> Result<(), Box<dyn std::error
     };
     // --- 修改结束 ---
 
+    // 在 `ChatSession::new` 新建一条对话记录之前，先看看要不要恢复上一次的对话，
+    // 否则"最近一次更新的对话"查到的永远是刚刚新建的这条空对话。
+    let resumed = if args.resume {
+        crate::conversation_store::CONVERSATION_STORE
+            .lock()
+            .unwrap()
+            .most_recent_conversation_id()
+            .ok()
+            .flatten()
+            .and_then(|id| {
+                crate::conversation_store::CONVERSATION_STORE
+                    .lock()
+                    .unwrap()
+                    .get_conversation(&id)
+                    .ok()
+                    .flatten()
+            })
+    } else {
+        None
+    };
+
     let mut session = ChatSession::new(engine);
+    if let Some(conversation) = resumed {
+        println!("{} {}", "Resuming conversation:".dimmed(), conversation.id);
+        session.resume(conversation);
+    }
     session.start()
 }
@@ -43,6 +43,20 @@ pub struct InferArgs {
     /// Context size
     /// Typical values are 512, 1024, 2048, etc.
     pub n_ctx: Option<i32>,
+    #[arg(long)]
+    /// Min-p sampling threshold
+    /// Typical values are between 0.0 (disabled) and 0.2
+    pub min_p: Option<f32>,
+    #[arg(long)]
+    /// Locally typical sampling mass
+    /// Typical values are between 0.0 and 1.0 (disabled)
+    pub typical_p: Option<f32>,
+    #[arg(long)]
+    /// RNG seed; omit for a random seed each run
+    pub seed: Option<u32>,
+    #[arg(long)]
+    /// Number of recent tokens considered by the repeat penalty
+    pub repeat_last_n: Option<i32>,
 }
 
 #[derive(Parser, Debug)]
@@ -56,4 +70,7 @@ pub struct DiscoverArgs {
 #[derive(Parser, Debug)]
 pub struct ChatArgs {
     pub model: String,
+    #[arg(long)]
+    /// Resume the most recently saved conversation instead of starting a new one
+    pub resume: bool,
 }
@@ -0,0 +1,157 @@
+//! 集中式的推理配置：过去 `ModelPool::get_model` 和 `chat_session` 各自用一份
+//! 硬编码的 `EngineConfig` 字面量，用户没有任何办法在不重新编译的情况下调整
+//! 上下文大小、采样参数或最大生成长度。本模块从 `TLLAMA_ENGINE_CONFIG_PATH`
+//! 指向的 JSON/TOML 文件里加载全局默认值与按模型名的覆盖项，`ModelPool` 和
+//! chat CLI 都应该通过 [`AppConfig::resolve`] 取得它们的 `EngineConfig`。
+
+use crate::engine::EngineConfig;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+lazy_static! {
+    pub static ref APP_CONFIG: AppConfig = AppConfig::load();
+}
+
+/// 每个字段都可选的 `EngineConfig` 补丁，用于在某个基础值之上做覆盖。
+/// 未出现的字段保留基础值不变；未知字段会在反序列化时报错，而不是被静默忽略。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EngineConfigPatch {
+    pub n_ctx: Option<i32>,
+    pub n_len: Option<u32>,
+    pub temperature: Option<f32>,
+    pub top_k: Option<i32>,
+    pub top_p: Option<f32>,
+    pub repeat_penalty: Option<f32>,
+    pub min_p: Option<f32>,
+    pub typical_p: Option<f32>,
+    pub seed: Option<u32>,
+    pub repeat_last_n: Option<i32>,
+    pub stop: Option<Vec<String>>,
+    pub presence_penalty: Option<f32>,
+    pub frequency_penalty: Option<f32>,
+    pub logit_bias: Option<HashMap<i32, f32>>,
+}
+
+impl EngineConfigPatch {
+    fn apply(&self, base: &EngineConfig) -> EngineConfig {
+        EngineConfig {
+            n_ctx: self.n_ctx.unwrap_or(base.n_ctx),
+            n_len: self.n_len.or(base.n_len),
+            temperature: self.temperature.unwrap_or(base.temperature),
+            top_k: self.top_k.unwrap_or(base.top_k),
+            top_p: self.top_p.unwrap_or(base.top_p),
+            repeat_penalty: self.repeat_penalty.unwrap_or(base.repeat_penalty),
+            min_p: self.min_p.unwrap_or(base.min_p),
+            typical_p: self.typical_p.unwrap_or(base.typical_p),
+            seed: self.seed.or(base.seed),
+            repeat_last_n: self.repeat_last_n.unwrap_or(base.repeat_last_n),
+            stop: self.stop.clone().unwrap_or_else(|| base.stop.clone()),
+            presence_penalty: self.presence_penalty.unwrap_or(base.presence_penalty),
+            frequency_penalty: self.frequency_penalty.unwrap_or(base.frequency_penalty),
+            logit_bias: self
+                .logit_bias
+                .clone()
+                .unwrap_or_else(|| base.logit_bias.clone()),
+        }
+    }
+}
+
+/// 不同场景下的最大生成 token 数。API 和交互式 chat 对"生成到什么时候该停"
+/// 往往有不同的预期，所以分开配置而不是共用同一个 `n_len`。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaxCompletionTokens {
+    pub api: Option<u32>,
+    pub chat: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AppConfig {
+    /// 覆盖内置默认值的全局补丁
+    #[serde(default)]
+    pub defaults: EngineConfigPatch,
+    #[serde(default)]
+    pub max_completion_tokens: MaxCompletionTokens,
+    /// 同时常驻内存的模型数上限，超出时 `ModelPool` 按 LRU 淘汰最久未用的模型。
+    /// 不配置则不限制，行为与引入淘汰策略之前一致。
+    #[serde(default)]
+    pub max_loaded_models: Option<usize>,
+    /// 允许同时在途的推理请求数上限（目前只有 `PythonBackend` 在用），
+    /// 用于在去掉全局串行锁之后仍能限制内存占用。不配置则不限制。
+    #[serde(default)]
+    pub max_in_flight_requests: Option<usize>,
+    /// 单次请求里 `n`/`best_of` 最多允许多大，超出的请求在
+    /// `api/openai.rs` 里会被直接拒绝，避免一次请求就把引擎的算力
+    /// 和内存占满。
+    #[serde(default = "default_max_client_batch_size")]
+    pub max_client_batch_size: usize,
+    /// 按模型名的覆盖项，键是 `Model.name`
+    #[serde(default)]
+    pub models: HashMap<String, EngineConfigPatch>,
+}
+
+fn default_max_client_batch_size() -> usize {
+    4
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            defaults: EngineConfigPatch::default(),
+            max_completion_tokens: MaxCompletionTokens::default(),
+            max_loaded_models: None,
+            max_in_flight_requests: None,
+            max_client_batch_size: default_max_client_batch_size(),
+            models: HashMap::new(),
+        }
+    }
+}
+
+impl AppConfig {
+    fn load() -> Self {
+        let Some(path) = crate::env::TLLAMA_ENGINE_CONFIG_PATH.as_ref() else {
+            return AppConfig::default();
+        };
+        if !path.exists() {
+            return AppConfig::default();
+        }
+
+        let content = std::fs::read_to_string(path).unwrap_or_else(|e| {
+            panic!("Failed to read engine config '{}': {}", path.display(), e)
+        });
+
+        let is_toml = path.extension().and_then(|ext| ext.to_str()) == Some("toml");
+        if is_toml {
+            toml::from_str(&content).unwrap_or_else(|e| {
+                panic!("Invalid engine config '{}': {}", path.display(), e)
+            })
+        } else {
+            serde_json::from_str(&content).unwrap_or_else(|e| {
+                panic!("Invalid engine config '{}': {}", path.display(), e)
+            })
+        }
+    }
+
+    /// 解析出某个具体模型应该使用的 `EngineConfig`：先把全局默认补丁叠加到
+    /// 平台相关的内置基础值上，再叠加该模型名对应的覆盖项（如果有的话）。
+    pub fn resolve(&self, model_name: &str) -> EngineConfig {
+        let base = self.defaults.apply(&platform_default_engine_config());
+        match self.models.get(model_name) {
+            Some(patch) => patch.apply(&base),
+            None => base,
+        }
+    }
+}
+
+/// 平台相关的基础默认值。macOS 上常见的统一内存机型更容易被过大的上下文
+/// 窗口拖垮，所以给一个更保守的默认 `n_ctx`。
+fn platform_default_engine_config() -> EngineConfig {
+    EngineConfig {
+        #[cfg(target_os = "macos")]
+        n_ctx: 2048,
+        ..Default::default()
+    }
+}
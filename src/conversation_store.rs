@@ -0,0 +1,231 @@
+//! 基于 SQLite 的持久化对话存储：`ChatSession` 原先只把历史保存在内存里的
+//! `Vec<Message>`，`.clear` 或进程退出都会把它和 system prompt 一起丢掉。
+//! 这里把每个对话（id、标题、system prompt、按顺序排列的消息及其角色/工具调用
+//! 元数据）落盘到一个 SQLite 文件；`ChatSession` 在每一轮对话结束时把新增的
+//! 消息 flush 进去，API 侧可以用同一张表创建/列出/读取对话。
+
+use lazy_static::lazy_static;
+use rusqlite::{Connection, OptionalExtension, params};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::template::{Message, MessageContent, ToolCall};
+
+lazy_static! {
+    pub static ref CONVERSATION_STORE: Mutex<ConversationStore> = Mutex::new(
+        ConversationStore::open(conversation_store_path()).unwrap_or_else(|e| {
+            panic!("Failed to open conversation store: {}", e)
+        })
+    );
+}
+
+fn conversation_store_path() -> PathBuf {
+    crate::env::TLLAMA_CONVERSATION_STORE_PATH
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("tllama-conversations.sqlite3"))
+}
+
+/// 一个对话的元信息，不含消息本体
+#[derive(Debug, Clone)]
+pub struct ConversationSummary {
+    pub id: String,
+    pub title: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// 一个完整对话：元信息 + 按顺序排列的消息
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    pub id: String,
+    pub title: String,
+    pub system_prompt: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub messages: Vec<Message>,
+}
+
+/// 对话的 SQLite 存储：一张 `conversations` 表存元信息，一张 `messages` 表
+/// 按 `(conversation_id, ordinal)` 存每一条消息
+pub struct ConversationStore {
+    conn: Connection,
+}
+
+impl ConversationStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+        if let Some(parent) = path.as_ref().parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS conversations (
+                id TEXT PRIMARY KEY,
+                title TEXT NOT NULL,
+                system_prompt TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS messages (
+                conversation_id TEXT NOT NULL REFERENCES conversations(id),
+                ordinal INTEGER NOT NULL,
+                role TEXT NOT NULL,
+                content TEXT,
+                tool_calls TEXT,
+                name TEXT,
+                PRIMARY KEY (conversation_id, ordinal)
+            );",
+        )?;
+        Ok(ConversationStore { conn })
+    }
+
+    /// 新建一个对话并立即落盘
+    pub fn create_conversation(
+        &self,
+        title: &str,
+        system_prompt: &str,
+    ) -> rusqlite::Result<Conversation> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let now = now_secs();
+        self.conn.execute(
+            "INSERT INTO conversations (id, title, system_prompt, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?4)",
+            params![id, title, system_prompt, now],
+        )?;
+        Ok(Conversation {
+            id,
+            title: title.to_string(),
+            system_prompt: system_prompt.to_string(),
+            created_at: now,
+            updated_at: now,
+            messages: Vec::new(),
+        })
+    }
+
+    /// 把一条消息追加到某个对话末尾，并刷新该对话的 `updated_at`
+    pub fn append_message(&self, conversation_id: &str, message: &Message) -> rusqlite::Result<()> {
+        let ordinal: i64 = self.conn.query_row(
+            "SELECT COALESCE(MAX(ordinal), -1) + 1 FROM messages WHERE conversation_id = ?1",
+            params![conversation_id],
+            |row| row.get(0),
+        )?;
+        // `content` 现在是带 `Text`/`Parts` 变体的枚举，和 `tool_calls` 一样
+        // 序列化成 JSON 字符串存进 TEXT 列，读取时再反序列化回来。
+        let content = message
+            .content
+            .as_ref()
+            .map(|content| serde_json::to_string(content).unwrap_or_default());
+        let tool_calls = message
+            .tool_calls
+            .as_ref()
+            .map(|calls| serde_json::to_string(calls).unwrap_or_default());
+        self.conn.execute(
+            "INSERT INTO messages (conversation_id, ordinal, role, content, tool_calls, name)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                conversation_id,
+                ordinal,
+                message.role,
+                content,
+                tool_calls,
+                message.name
+            ],
+        )?;
+        self.conn.execute(
+            "UPDATE conversations SET updated_at = ?2 WHERE id = ?1",
+            params![conversation_id, now_secs()],
+        )?;
+        Ok(())
+    }
+
+    /// 更新对话标题
+    pub fn rename_conversation(&self, conversation_id: &str, title: &str) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "UPDATE conversations SET title = ?2 WHERE id = ?1",
+            params![conversation_id, title],
+        )?;
+        Ok(())
+    }
+
+    /// 按最近更新时间倒序列出所有已保存的对话
+    pub fn list_conversations(&self) -> rusqlite::Result<Vec<ConversationSummary>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, title, created_at, updated_at FROM conversations ORDER BY updated_at DESC",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(ConversationSummary {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                created_at: row.get(2)?,
+                updated_at: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// 最近一次更新的对话 id，供启动时恢复会话使用
+    pub fn most_recent_conversation_id(&self) -> rusqlite::Result<Option<String>> {
+        self.conn
+            .query_row(
+                "SELECT id FROM conversations ORDER BY updated_at DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// 读取一个对话的完整内容，包括按顺序排列的消息；不存在则返回 `None`
+    pub fn get_conversation(&self, conversation_id: &str) -> rusqlite::Result<Option<Conversation>> {
+        let header = self
+            .conn
+            .query_row(
+                "SELECT title, system_prompt, created_at, updated_at FROM conversations WHERE id = ?1",
+                params![conversation_id],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, i64>(3)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((title, system_prompt, created_at, updated_at)) = header else {
+            return Ok(None);
+        };
+
+        let mut stmt = self.conn.prepare(
+            "SELECT role, content, tool_calls, name FROM messages
+             WHERE conversation_id = ?1 ORDER BY ordinal",
+        )?;
+        let messages = stmt
+            .query_map(params![conversation_id], |row| {
+                let content: Option<String> = row.get(1)?;
+                let tool_calls: Option<String> = row.get(2)?;
+                Ok(Message {
+                    role: row.get(0)?,
+                    content: content.and_then(|s| serde_json::from_str::<MessageContent>(&s).ok()),
+                    tool_calls: tool_calls
+                        .and_then(|s| serde_json::from_str::<Vec<ToolCall>>(&s).ok()),
+                    name: row.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(Some(Conversation {
+            id: conversation_id.to_string(),
+            title,
+            system_prompt,
+            created_at,
+            updated_at,
+            messages,
+        }))
+    }
+}
+
+fn now_secs() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
@@ -1,13 +1,16 @@
+use crate::gguf;
+use crate::safetensors;
 use glob::Pattern;
 use lazy_static::lazy_static;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs;
-use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
 use walkdir::WalkDir;
 
 lazy_static! {
@@ -22,6 +25,9 @@ lazy_static! {
 pub enum ModelType {
     Gguf,
     Transformers,
+    /// 模型实际运行在一个远程的、OpenAI 兼容的 HTTP 服务器上。对这类 `Model`，
+    /// `path` 存放上游服务器的 base URL，`name` 是转发请求时使用的远程模型 id。
+    Remote,
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Model {
@@ -30,6 +36,18 @@ pub struct Model {
     pub name: String,
     pub size: u64,
     pub template: Option<String>,
+    /// 模型架构，来自 GGUF 的 `general.architecture` 或 HF `config.json` 的
+    /// `architectures[0]`/`model_type`
+    pub architecture: Option<String>,
+    /// 量化类型提示，来自 GGUF 的 `general.file_type`（如 `Q4_K_M`）或
+    /// safetensors `__metadata__` 中的量化相关字段
+    pub quant_hint: Option<String>,
+    /// 训练上下文长度，来自 GGUF 的 `<arch>.context_length` 或 HF
+    /// `config.json` 的 `max_position_embeddings`
+    pub context_length: Option<u32>,
+    /// 张量的存储精度，来自 safetensors 头部张量条目的 `dtype` 或 HF
+    /// `config.json` 的 `torch_dtype`，GGUF 模型为 `None`
+    pub dtype: Option<String>,
 }
 
 impl Model {
@@ -40,357 +58,799 @@ impl Model {
             format: ModelType::Gguf,
             size: 0,
             template: None,
+            architecture: None,
+            quant_hint: None,
+            context_length: None,
+            dtype: None,
         }
     }
 }
 
+/// 单个搜索路径下目录级发现结果的缓存条目（用于 Ollama/HuggingFace 特殊分支）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PathCacheEntry {
+    /// 该路径下所有文件数量与最新修改时间组合出的简单指纹
+    fingerprint: u64,
+    models: Vec<Model>,
+}
+
+/// 单个文件嗅探结果的缓存条目（用于通用 GGUF/safetensors 分支）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FileCacheEntry {
+    mtime: u64,
+    model: Model,
+}
+
+/// 发现结果的磁盘缓存，按搜索路径写入用户缓存目录，避免每次启动都全量重新扫描/嗅探
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct DiscoveryCache {
+    path_entries: HashMap<String, PathCacheEntry>,
+    file_entries: HashMap<String, FileCacheEntry>,
+}
+
+fn discovery_cache_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|c| c.join("tllama").join("discover_cache.json"))
+}
+
+fn load_discovery_cache() -> DiscoveryCache {
+    let Some(path) = discovery_cache_path() else {
+        return DiscoveryCache::default();
+    };
+    let Ok(content) = fs::read_to_string(&path) else {
+        return DiscoveryCache::default();
+    };
+    serde_json::from_str(&content).unwrap_or_default()
+}
+
+fn save_discovery_cache(cache: &DiscoveryCache) {
+    let Some(path) = discovery_cache_path() else {
+        return;
+    };
+    if let Some(parent) = path.parent() {
+        if fs::create_dir_all(parent).is_err() {
+            return;
+        }
+    }
+    if let Ok(content) = serde_json::to_string(cache) {
+        let _ = fs::write(path, content);
+    }
+}
+
+/// 将文件的最后修改时间转换为自 UNIX 纪元以来的秒数
+fn mtime_secs(path: &Path) -> Option<u64> {
+    path.metadata()
+        .ok()?
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+/// 对一个目录树组合出简单指纹（文件数量 + 最新修改时间），用于判断目录级别的缓存是否失效
+fn dir_fingerprint(path: &Path) -> u64 {
+    let mut count: u64 = 0;
+    let mut latest_mtime: u64 = 0;
+    for entry in WalkDir::new(path).into_iter().filter_map(Result::ok) {
+        if let Ok(meta) = entry.metadata() {
+            count += 1;
+            if let Some(secs) = meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+            {
+                latest_mtime = latest_mtime.max(secs);
+            }
+        }
+    }
+    (count << 40) ^ latest_mtime
+}
+
+fn path_key(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+fn expand_home(pattern: &str) -> String {
+    if let Some(rest) = pattern.strip_prefix("~/") {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(rest).to_string_lossy().to_string();
+        }
+    }
+    pattern.to_string()
+}
+
+/// 将一个 include glob（如 `~/ml/**/*.gguf`）拆分为一个不含通配符的基准目录，
+/// 以及描述其余部分的可选 `Pattern`。`WalkDir` 只从基准目录开始遍历，
+/// 真正的通配符匹配留到遍历期间按需进行，避免提前展开整棵树。
+fn split_include_pattern(pattern: &str) -> (PathBuf, Option<Pattern>) {
+    let expanded = expand_home(pattern);
+    let is_glob_meta = |s: &str| s.contains('*') || s.contains('?') || s.contains('[');
+
+    let mut base = PathBuf::new();
+    let mut rest: Vec<String> = Vec::new();
+    let mut in_pattern = false;
+    for component in Path::new(&expanded).components() {
+        let component_str = component.as_os_str().to_string_lossy().to_string();
+        if !in_pattern && is_glob_meta(&component_str) {
+            in_pattern = true;
+        }
+        if in_pattern {
+            rest.push(component_str);
+        } else {
+            base.push(component.as_os_str());
+        }
+    }
+
+    if rest.is_empty() {
+        return (base, None);
+    }
+
+    let full_pattern = format!("{}/{}", base.to_string_lossy(), rest.join("/"));
+    (base, Pattern::new(&full_pattern).ok())
+}
+
+fn parse_include_patterns(patterns: &[String]) -> Vec<(PathBuf, Option<Pattern>)> {
+    patterns.iter().map(|p| split_include_pattern(p)).collect()
+}
+
+fn parse_exclude_patterns(patterns: &[String]) -> Vec<Pattern> {
+    patterns
+        .iter()
+        .filter_map(|p| Pattern::new(&expand_home(p)).ok())
+        .collect()
+}
+
+fn is_path_excluded(patterns: &[Pattern], path: &Path) -> bool {
+    patterns.iter().any(|p| p.matches_path(path))
+}
+
+/// 一个发现配置层，`None` 表示该层没有触碰这个键，`Some` 表示显式设置（可能是 `%unset` 产生的空值）
+#[derive(Debug, Clone, Default)]
+struct ConfigLayer {
+    search_paths: Option<Vec<PathBuf>>,
+    exclude: Option<Vec<String>>,
+}
+
+fn resolve_config_value(base_dir: &Path, value: &str) -> PathBuf {
+    let expanded = expand_home(value);
+    let candidate = PathBuf::from(&expanded);
+    if candidate.is_absolute() {
+        candidate
+    } else {
+        base_dir.join(candidate)
+    }
+}
+
+/// 解析一个分层发现配置文件，支持 `%include <path>`（在该位置拼接另一个文件的层，
+/// 其中的相对路径相对于被包含文件所在目录解析）和 `%unset <key>`（清除之前设置的值）。
+/// 每个文件按其中的 `%include` 切分成结果中的若干层（`%include` 之前、之后的
+/// 内容各成一层）；调用方按顺序合并这些层，后面的层覆盖前面的层。
+///
+/// `visited` 记录这次解析过程中已经展开过的文件（规范化后的路径），防止一个
+/// 文件直接或间接 `%include` 自己时无限递归、最终栈溢出；撞上环直接跳过这个
+/// `%include`，和其他格式错误的行一样静默忽略，不中断其余层的解析。
+fn load_config_layers_into(path: &Path, layers: &mut Vec<ConfigLayer>, visited: &mut HashSet<PathBuf>) {
+    let Ok(content) = fs::read_to_string(path) else {
+        return;
+    };
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if !visited.insert(canonical) {
+        return;
+    }
+    let base_dir = path.parent().map(PathBuf::from).unwrap_or_default();
+    let mut layer = ConfigLayer::default();
+
+    for raw_line in content.lines() {
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(include_path) = line.strip_prefix("%include ") {
+            let resolved = resolve_config_value(&base_dir, include_path.trim());
+            layers.push(std::mem::take(&mut layer));
+            load_config_layers_into(&resolved, layers, visited);
+            continue;
+        }
+        if let Some(key) = line.strip_prefix("%unset ") {
+            match key.trim() {
+                "search_paths" => layer.search_paths = Some(Vec::new()),
+                "exclude" => layer.exclude = Some(Vec::new()),
+                _ => {}
+            }
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let values = value.split(',').map(|v| v.trim()).filter(|v| !v.is_empty());
+        match key.trim() {
+            "search_paths" => {
+                layer
+                    .search_paths
+                    .get_or_insert_with(Vec::new)
+                    .extend(values.map(|v| resolve_config_value(&base_dir, v)));
+            }
+            "exclude" => {
+                layer
+                    .exclude
+                    .get_or_insert_with(Vec::new)
+                    .extend(values.map(|v| v.to_string()));
+            }
+            _ => {}
+        }
+    }
+
+    layers.push(layer);
+}
+
+/// 加载并合并 `TLLAMA_CONFIG_PATH` 指向的分层配置文件（若存在）
+fn load_layered_config() -> ConfigLayer {
+    let mut merged = ConfigLayer::default();
+    let Some(config_path) = crate::env::TLLAMA_CONFIG_PATH.as_ref() else {
+        return merged;
+    };
+    if !config_path.is_file() {
+        return merged;
+    }
+
+    let mut layers = Vec::new();
+    load_config_layers_into(config_path, &mut layers, &mut HashSet::new());
+    for layer in layers {
+        if let Some(search_paths) = layer.search_paths {
+            merged.search_paths = Some(search_paths);
+        }
+        if let Some(exclude) = layer.exclude {
+            merged.exclude = Some(exclude);
+        }
+    }
+    merged
+}
+
 pub struct ModelDiscover {
     model_list: Vec<Model>,
     scan_all_paths: bool,
+    num_threads: usize,
+    include_rules: Vec<(PathBuf, Option<Pattern>)>,
+    exclude_patterns: Vec<Pattern>,
+    /// 分层配置文件解析出的额外搜索路径，已解析为绝对/相对于各自文件的路径
+    config_search_paths: Vec<PathBuf>,
 }
 
 impl ModelDiscover {
     pub fn new() -> Self {
+        let config = load_layered_config();
+        let mut exclude_patterns = parse_exclude_patterns(&crate::env::TLLAMA_EXCLUDE_GLOBS);
+        if let Some(exclude) = &config.exclude {
+            exclude_patterns.extend(parse_exclude_patterns(exclude));
+        }
+
         ModelDiscover {
             model_list: Vec::new(),
             scan_all_paths: false,
+            num_threads: *crate::env::TLLAMA_DISCOVER_THREADS as usize,
+            include_rules: parse_include_patterns(&crate::env::TLLAMA_INCLUDE_GLOBS),
+            exclude_patterns,
+            config_search_paths: config.search_paths.unwrap_or_default(),
         }
     }
 
     pub fn scan_all_paths(&mut self, scan: bool) {
         self.scan_all_paths = scan;
     }
+
+    /// 设置发现阶段使用的 Rayon 线程数，调用方可以借此限制并发度
+    pub fn num_threads(&mut self, num_threads: usize) {
+        self.num_threads = num_threads;
+    }
+
+    /// 设置用户自定义的 include glob 规则（如 `~/ml/**/*.gguf`），覆盖环境变量配置
+    pub fn include_patterns(&mut self, patterns: &[String]) {
+        self.include_rules = parse_include_patterns(patterns);
+    }
+
+    /// 设置用户自定义的 exclude glob 规则（如 `**/node_modules/**`），覆盖环境变量配置
+    pub fn exclude_patterns(&mut self, patterns: &[String]) {
+        self.exclude_patterns = parse_exclude_patterns(patterns);
+    }
+
     /// core method, scan model directory
     pub fn discover(&mut self) {
-        self.model_list.clear();
         let search_paths = self.make_search_paths(true);
-        for path in search_paths {
-            if directory_has_features(&path, &["manifests", "blobs", "blobs/sha256-*"]) {
-                // Ollama Models
-                self.discover_ollama_models(&path.as_path());
-                continue;
-            }
-            if directory_has_features(&path, &["*/blobs", "*/refs", "*/snapshots"]) {
-                // HuggingFace Cached Models
-                self.discover_hf_models(&path);
-                continue;
-            }
-            for entry in WalkDir::new(&path)
-                .into_iter()
-                .filter_map(Result::ok)
-                .filter(|e| e.file_type().is_file())
-            {
-                let full_path = entry.path();
-                if self.check_exclude(&full_path) {
-                    continue;
-                }
-
-                match full_path.metadata() {
-                    Ok(meta) => {
-                        if meta.len() < 50 * 1024 * 1024 {
-                            // 文件小于 50MB，跳过
-                            continue;
-                        }
+        let scan_all_paths = self.scan_all_paths;
+        let include_rules = self.include_rules.clone();
+        let exclude_patterns = self.exclude_patterns.clone();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.num_threads)
+            .build()
+            .expect("Failed to build model-discovery thread pool");
+
+        let old_cache = Arc::new(load_discovery_cache());
+        let new_cache = Arc::new(Mutex::new(DiscoveryCache::default()));
+
+        self.model_list = pool.install(|| {
+            search_paths
+                .par_iter()
+                .flat_map(|path| -> Vec<Model> {
+                    if directory_has_features(path, &["manifests", "blobs", "blobs/sha256-*"]) {
+                        // Ollama Models
+                        return Self::discover_with_path_cache(
+                            path,
+                            &path.join("manifests"),
+                            &old_cache,
+                            &new_cache,
+                            discover_ollama_models,
+                        );
                     }
-                    Err(_) => continue,
-                }
-                if self.check_gguf_format(&full_path) {
-                    self.model_list.push(Model {
-                        name: full_path.file_stem().unwrap().to_string_lossy().to_string(),
-                        format: ModelType::Gguf,
-                        path: path.to_path_buf(),
-                        size: full_path.metadata().unwrap().len(),
-                        template: None,
-                    });
-                } else if self.check_safetensors_format(&full_path) {
-                    self.model_list.push(Model {
-                        name: full_path.file_stem().unwrap().to_string_lossy().to_string(),
-                        format: ModelType::Transformers,
-                        path: path.to_path_buf(),
-                        size: full_path.metadata().unwrap().len(),
-                        template: None,
-                    });
-                } else {
-                    continue;
-                }
-            }
+                    if directory_has_features(path, &["*/blobs", "*/refs", "*/snapshots"]) {
+                        // HuggingFace Cached Models
+                        return Self::discover_with_path_cache(
+                            path,
+                            path,
+                            &old_cache,
+                            &new_cache,
+                            discover_hf_models,
+                        );
+                    }
+
+                    // 该搜索路径是否来自某条 include 规则，若是则要求文件同时匹配其剩余通配符
+                    let include_pattern = include_rules
+                        .iter()
+                        .find(|(base, _)| base == path)
+                        .and_then(|(_, pattern)| pattern.clone());
+
+                    WalkDir::new(path)
+                        .into_iter()
+                        // 在遍历期间按目录剪枝被排除的子树，而不是枚举完所有文件之后再过滤
+                        .filter_entry(|e| !is_path_excluded(&exclude_patterns, e.path()))
+                        .filter_map(Result::ok)
+                        .filter(|e| e.file_type().is_file())
+                        .filter(|e| {
+                            include_pattern
+                                .as_ref()
+                                .map_or(true, |p| p.matches_path(e.path()))
+                        })
+                        .collect::<Vec<_>>()
+                        .into_par_iter()
+                        .filter_map(|entry| {
+                            let full_path = entry.path();
+                            if check_exclude(scan_all_paths, full_path) {
+                                return None;
+                            }
+                            let key = path_key(full_path);
+                            let mtime = mtime_secs(full_path)?;
+
+                            if let Some(cached) = old_cache.file_entries.get(&key) {
+                                if cached.mtime == mtime {
+                                    new_cache
+                                        .lock()
+                                        .unwrap()
+                                        .file_entries
+                                        .insert(key, cached.clone());
+                                    return Some(cached.model.clone());
+                                }
+                            }
+
+                            match full_path.metadata() {
+                                Ok(meta) => {
+                                    if meta.len() < 50 * 1024 * 1024 {
+                                        // 文件小于 50MB，跳过
+                                        return None;
+                                    }
+                                }
+                                Err(_) => return None,
+                            }
+
+                            let model = if let Some(meta) = gguf::read_gguf_metadata(full_path) {
+                                Some(Model {
+                                    name: meta.name.unwrap_or_else(|| {
+                                        full_path
+                                            .file_stem()
+                                            .unwrap()
+                                            .to_string_lossy()
+                                            .to_string()
+                                    }),
+                                    format: ModelType::Gguf,
+                                    path: path.to_path_buf(),
+                                    size: full_path.metadata().unwrap().len(),
+                                    template: meta.chat_template,
+                                    architecture: meta.architecture,
+                                    quant_hint: meta.quant_hint,
+                                    context_length: meta.context_length,
+                                    dtype: None,
+                                })
+                            } else if let Some(meta) =
+                                safetensors::read_safetensors_metadata(full_path)
+                            {
+                                Some(Model {
+                                    name: full_path
+                                        .file_stem()
+                                        .unwrap()
+                                        .to_string_lossy()
+                                        .to_string(),
+                                    format: ModelType::Transformers,
+                                    path: path.to_path_buf(),
+                                    size: full_path.metadata().unwrap().len(),
+                                    template: None,
+                                    architecture: meta.architecture,
+                                    quant_hint: meta.quant_hint,
+                                    context_length: None,
+                                    dtype: meta.dtype,
+                                })
+                            } else {
+                                None
+                            };
+
+                            if let Some(model) = &model {
+                                new_cache.lock().unwrap().file_entries.insert(
+                                    key,
+                                    FileCacheEntry {
+                                        mtime,
+                                        model: model.clone(),
+                                    },
+                                );
+                            }
+                            model
+                        })
+                        .collect()
+                })
+                .collect()
+        });
+
+        if let Ok(cache) = Arc::try_unwrap(new_cache).map(|m| m.into_inner().unwrap()) {
+            save_discovery_cache(&cache);
         }
     }
 
-    fn discover_ollama_models(&mut self, path: &Path) {
-        let manifests_path = path.join("manifests");
-        if !manifests_path.is_dir() {
-            return;
+    /// 对 Ollama/HuggingFace 这类"目录级"发现分支应用基于指纹的缓存：
+    /// 若目录树的指纹（文件数量 + 最新修改时间）未变，直接复用上一次的 `Model` 列表，
+    /// 否则回退到完整的重新扫描。命中与未命中都会把结果写回 `new_cache`。
+    fn discover_with_path_cache(
+        top_level_path: &Path,
+        fingerprint_path: &Path,
+        old_cache: &DiscoveryCache,
+        new_cache: &Mutex<DiscoveryCache>,
+        rescan: fn(&Path) -> Vec<Model>,
+    ) -> Vec<Model> {
+        let key = path_key(top_level_path);
+        let fingerprint = dir_fingerprint(fingerprint_path);
+
+        let models = match old_cache.path_entries.get(&key) {
+            Some(cached) if cached.fingerprint == fingerprint => cached.models.clone(),
+            _ => rescan(top_level_path),
+        };
+
+        new_cache.lock().unwrap().path_entries.insert(
+            key,
+            PathCacheEntry {
+                fingerprint,
+                models: models.clone(),
+            },
+        );
+        models
+    }
+
+    /// 获取发现的模型列表的只读引用 (无变化)
+    pub fn get_model_list(&self) -> &Vec<Model> {
+        &self.model_list
+    }
+
+    pub fn find_model(&self, model_name: &str) -> Result<Model, Box<dyn std::error::Error>> {
+        for model in &self.model_list {
+            if model.name == model_name {
+                return Ok(model.clone());
+            }
         }
-        for entry in WalkDir::new(&manifests_path)
-            .into_iter()
-            .filter_map(Result::ok)
-            .filter(|e| e.file_type().is_file())
-        {
-            let full_path = entry.path();
-            let file_rel_path = match full_path.strip_prefix(&manifests_path) {
-                Ok(p) => p,
-                Err(_) => continue,
-            };
+        Err(format!("Model {} not found", model_name).into())
+    }
+}
 
-            let mut components: Vec<&str> = file_rel_path
-                .components()
-                .filter_map(|c| c.as_os_str().to_str())
-                .collect();
-            if components.is_empty() {
-                continue;
+fn discover_ollama_models(path: &Path) -> Vec<Model> {
+    let mut models = Vec::new();
+    let manifests_path = path.join("manifests");
+    if !manifests_path.is_dir() {
+        return models;
+    }
+    for entry in WalkDir::new(&manifests_path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+    {
+        let full_path = entry.path();
+        let file_rel_path = match full_path.strip_prefix(&manifests_path) {
+            Ok(p) => p,
+            Err(_) => continue,
+        };
+
+        let mut components: Vec<&str> = file_rel_path
+            .components()
+            .filter_map(|c| c.as_os_str().to_str())
+            .collect();
+        if components.is_empty() {
+            continue;
+        }
+        // [新逻辑] 检查域名部分
+        let domain = components[0];
+        if domain == "registry.ollama.ai" {
+            // 仅当域名是官方注册表时，我们才简化名称
+            components.remove(0); // 移除 "registry.ollama.ai"
+            if !components.is_empty() && components[0] == "library" {
+                components.remove(0); // 移除 "library"
             }
-            // [新逻辑] 检查域名部分
-            let domain = components[0];
-            if domain == "registry.ollama.ai" {
-                // 仅当域名是官方注册表时，我们才简化名称
-                components.remove(0); // 移除 "registry.ollama.ai"
-                if !components.is_empty() && components[0] == "library" {
-                    components.remove(0); // 移除 "library"
+        }
+        // 对于所有其他域名 (e.g., "localhost", "my-registry.com")，
+        // 我们保留完整的路径来避免命名冲突，所以不做任何操作。
+
+        if components.len() < 2 {
+            // 至少需要 model_name 和 tag
+            continue;
+        }
+        // 将最后一部分（标签）与前面的部分（模型名）用 ':' 连接
+        let tag = components.pop().unwrap(); // 安全的 unwrap，因为已检查 len >= 2
+        let model_repo = components.join("/");
+        let model_name = format!("{}:{}", model_repo, tag);
+
+        let json_content = match std::fs::read_to_string(full_path) {
+            Ok(json) => json,
+            Err(_) => continue,
+        };
+        let manifest: Value = match serde_json::from_str(&json_content) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        let model_size: u64 = manifest["layers"].as_array().map_or(0, |layers| {
+            layers
+                .iter()
+                .filter_map(|layer| layer["size"].as_i64())
+                .sum()
+        }) as u64;
+        if model_size == 0 {
+            continue;
+        }
+        let model_template: Option<String> = manifest["layers"] // <-- 修正拼写
+            .as_array()
+            .and_then(|layers| {
+                // 1. 找到包含模板信息的 layer
+                layers
+                    .iter()
+                    .find(|layer| layer["mediaType"] == "application/vnd.ollama.image.template")
+            })
+            .and_then(|template_layer| {
+                // 2. 从该 layer 中获取 digest (e.g., "sha256:abcdef...")
+                template_layer["digest"].as_str()
+            })
+            .and_then(|digest| {
+                // 3. 将 digest 转换为 blob 文件名 (e.g., "sha256-abcdef...")
+                let blob_filename = digest.replace(':', "-");
+                let blob_path = path.join("blobs").join(blob_filename);
+
+                // 4. 读取 blob 文件的内容，这正是模板字符串
+                fs::read_to_string(blob_path).ok()
+            });
+
+        let model_path = manifest["layers"]
+            .as_array()
+            .and_then(|layers| {
+                // 1. 找到包含模板信息的 layer
+                layers
+                    .iter()
+                    .find(|layer| layer["mediaType"] == "application/vnd.ollama.image.model")
+            })
+            .and_then(|template_layer| {
+                // 2. 从该 layer 中获取 digest (e.g., "sha256:abcdef...")
+                template_layer["digest"].as_str()
+            })
+            .and_then(|digest| {
+                // 3. 将 digest 转换为 blob 文件名 (e.g., "sha256-abcdef...")
+                let blob_filename = digest.replace(':', "-");
+                let p = path.join("blobs").join(blob_filename);
+                if !p.exists() {
+                    return None;
                 }
-            }
-            // 对于所有其他域名 (e.g., "localhost", "my-registry.com")，
-            // 我们保留完整的路径来避免命名冲突，所以不做任何操作。
+                Some(p.to_path_buf())
+            });
+        let model_path = match model_path {
+            Some(p) => p,
+            None => continue,
+        };
 
-            if components.len() < 2 {
-                // 至少需要 model_name 和 tag
+        let gguf_meta = gguf::read_gguf_metadata(&model_path);
+        let model = Model {
+            format: ModelType::Gguf,
+            path: model_path,
+            name: model_name,
+            size: model_size,
+            template: model_template.or_else(|| gguf_meta.as_ref().and_then(|m| m.chat_template.clone())),
+            architecture: gguf_meta.as_ref().and_then(|m| m.architecture.clone()),
+            quant_hint: gguf_meta.as_ref().and_then(|m| m.quant_hint.clone()),
+            context_length: gguf_meta.as_ref().and_then(|m| m.context_length),
+            dtype: None,
+        };
+        models.push(model);
+    }
+    models
+}
+
+fn discover_hf_models(path: &Path) -> Vec<Model> {
+    let mut models = Vec::new();
+    for model in path.read_dir().expect("Failed to read directory") {
+        if let Ok(entry) = model {
+            let model_dir = entry.path();
+            if !entry.file_type().map_or(false, |ft| ft.is_dir())
+                || !entry.file_name().to_string_lossy().starts_with("models--")
+            {
                 continue;
             }
-            // 将最后一部分（标签）与前面的部分（模型名）用 ':' 连接
-            let tag = components.pop().unwrap(); // 安全的 unwrap，因为已检查 len >= 2
-            let model_repo = components.join("/");
-            let model_name = format!("{}:{}", model_repo, tag);
-
-            let json_content = match std::fs::read_to_string(full_path) {
-                Ok(json) => json,
-                Err(_) => continue,
-            };
-            let manifest: Value = match serde_json::from_str(&json_content) {
-                Ok(v) => v,
-                Err(_) => continue,
-            };
 
-            let model_size: u64 = manifest["layers"].as_array().map_or(0, |layers| {
-                layers
-                    .iter()
-                    .filter_map(|layer| layer["size"].as_i64())
-                    .sum()
-            }) as u64;
-            if model_size == 0 {
+            // 检查是否包含必要的文件
+            if !directory_has_features(
+                &model_dir,
+                &[
+                    "snapshots/*/config.json",
+                    "snapshots/*/tokenizer_config.json",
+                ],
+            ) {
                 continue;
             }
-            let model_template: Option<String> = manifest["layers"] // <-- 修正拼写
-                .as_array()
-                .and_then(|layers| {
-                    // 1. 找到包含模板信息的 layer
-                    layers
-                        .iter()
-                        .find(|layer| layer["mediaType"] == "application/vnd.ollama.image.template")
-                })
-                .and_then(|template_layer| {
-                    // 2. 从该 layer 中获取 digest (e.g., "sha256:abcdef...")
-                    template_layer["digest"].as_str()
-                })
-                .and_then(|digest| {
-                    // 3. 将 digest 转换为 blob 文件名 (e.g., "sha256-abcdef...")
-                    let blob_filename = digest.replace(':', "-");
-                    let blob_path = path.join("blobs").join(blob_filename);
-
-                    // 4. 读取 blob 文件的内容，这正是模板字符串
-                    fs::read_to_string(blob_path).ok()
-                });
-
-            let model_path = manifest["layers"]
-                .as_array()
-                .and_then(|layers| {
-                    // 1. 找到包含模板信息的 layer
-                    layers
-                        .iter()
-                        .find(|layer| layer["mediaType"] == "application/vnd.ollama.image.model")
-                })
-                .and_then(|template_layer| {
-                    // 2. 从该 layer 中获取 digest (e.g., "sha256:abcdef...")
-                    template_layer["digest"].as_str()
-                })
-                .and_then(|digest| {
-                    // 3. 将 digest 转换为 blob 文件名 (e.g., "sha256-abcdef...")
-                    let blob_filename = digest.replace(':', "-");
-                    let p = path.join("blobs").join(blob_filename);
-                    if !p.exists() {
-                        return None;
-                    }
-                    Some(p.to_path_buf())
-                });
-            let model_path = match model_path {
-                Some(p) => p,
-                None => continue,
-            };
 
-            let model = Model {
-                format: ModelType::Gguf,
-                path: model_path,
-                name: model_name,
-                size: model_size,
-                template: model_template,
+            // 解析模型名称：models--owner--repo -> owner/repo
+            let file_name = entry.file_name();
+            let file_name_str = file_name.to_string_lossy();
+            let stripped = &file_name_str["models--".len()..];
+            let parts: Vec<&str> = stripped.splitn(2, "--").collect();
+            let model_name = if parts.len() == 2 {
+                format!("{}/{}", parts[0], parts[1].replace("--", "/"))
+            } else {
+                stripped.replace("--", "/")
             };
-            self.model_list.push(model);
-        }
-    }
-
-    fn discover_hf_models(&mut self, path: &Path) {
-        for model in path.read_dir().expect("Failed to read directory") {
-            if let Ok(entry) = model {
-                let model_dir = entry.path();
-                if !entry.file_type().map_or(false, |ft| ft.is_dir())
-                    || !entry.file_name().to_string_lossy().starts_with("models--")
-                {
-                    continue;
-                }
 
-                // 检查是否包含必要的文件
-                if !directory_has_features(
-                    &model_dir,
-                    &[
-                        "snapshots/*/config.json",
-                        "snapshots/*/tokenizer_config.json",
-                    ],
-                ) {
-                    continue;
-                }
-
-                // 解析模型名称：models--owner--repo -> owner/repo
-                let file_name = entry.file_name();
-                let file_name_str = file_name.to_string_lossy();
-                let stripped = &file_name_str["models--".len()..];
-                let parts: Vec<&str> = stripped.splitn(2, "--").collect();
-                let model_name = if parts.len() == 2 {
-                    format!("{}/{}", parts[0], parts[1].replace("--", "/"))
-                } else {
-                    stripped.replace("--", "/")
-                };
-
-                // 查找 snapshot 目录下的所有快照（通常只有一个，但支持多个）
-                let snapshot_path = model_dir.join("snapshots");
-                if !snapshot_path.is_dir() {
-                    continue;
-                }
+            // 查找 snapshot 目录下的所有快照（通常只有一个，但支持多个）
+            let snapshot_path = model_dir.join("snapshots");
+            if !snapshot_path.is_dir() {
+                continue;
+            }
 
-                for snapshot in snapshot_path.read_dir().expect("Failed to read snapshots") {
-                    if let Ok(snapshot_entry) = snapshot {
-                        if !snapshot_entry.file_type().map_or(false, |ft| ft.is_dir()) {
-                            continue;
-                        }
+            for snapshot in snapshot_path.read_dir().expect("Failed to read snapshots") {
+                if let Ok(snapshot_entry) = snapshot {
+                    if !snapshot_entry.file_type().map_or(false, |ft| ft.is_dir()) {
+                        continue;
+                    }
 
-                        let snapshot_dir = snapshot_entry.path();
-                        let tokenizer_config_path = snapshot_dir.join("tokenizer_config.json");
-
-                        // 读取 chat template
-                        let chat_template = if tokenizer_config_path.exists() {
-                            if let Ok(content) = fs::read_to_string(&tokenizer_config_path) {
-                                if let Ok(json) = serde_json::from_str::<Value>(&content) {
-                                    json["chat_template"]
-                                        .as_str()
-                                        .map(|s| s.to_string())
-                                        .or_else(|| {
-                                            // 回退到特殊字段如 tokenizer.chat_template（罕见情况）
-                                            json.get("tokenizer")
-                                                .and_then(|t| t["chat_template"].as_str())
-                                                .map(|s| s.to_string())
-                                        })
-                                } else {
-                                    None
-                                }
+                    let snapshot_dir = snapshot_entry.path();
+                    let tokenizer_config_path = snapshot_dir.join("tokenizer_config.json");
+                    let config_path = snapshot_dir.join("config.json");
+
+                    // 读取 config.json 中的架构、上下文长度与存储精度
+                    let config_json = fs::read_to_string(&config_path)
+                        .ok()
+                        .and_then(|content| serde_json::from_str::<Value>(&content).ok());
+                    let architecture = config_json.as_ref().and_then(|json| {
+                        json["architectures"][0]
+                            .as_str()
+                            .or_else(|| json["model_type"].as_str())
+                            .map(str::to_string)
+                    });
+                    let context_length = config_json
+                        .as_ref()
+                        .and_then(|json| json["max_position_embeddings"].as_u64())
+                        .map(|v| v as u32);
+                    let dtype = config_json
+                        .as_ref()
+                        .and_then(|json| json["torch_dtype"].as_str())
+                        .map(str::to_string);
+
+                    // 读取 chat template
+                    let chat_template = if tokenizer_config_path.exists() {
+                        if let Ok(content) = fs::read_to_string(&tokenizer_config_path) {
+                            if let Ok(json) = serde_json::from_str::<Value>(&content) {
+                                json["chat_template"]
+                                    .as_str()
+                                    .map(|s| s.to_string())
+                                    .or_else(|| {
+                                        // 回退到特殊字段如 tokenizer.chat_template（罕见情况）
+                                        json.get("tokenizer")
+                                            .and_then(|t| t["chat_template"].as_str())
+                                            .map(|s| s.to_string())
+                                    })
                             } else {
                                 None
                             }
                         } else {
                             None
-                        };
-
-                        // 回退到默认模板
-                        let effective_template = chat_template
-                            .unwrap_or_else(|| crate::template::get_default_template());
-
-                        // 统计模型文件总大小
-                        let mut total_size: u64 = 0;
-                        let mut file_count = 0;
-                        for entry in WalkDir::new(&snapshot_dir)
-                            .into_iter()
-                            .filter_map(Result::ok)
-                            .filter(|e| e.file_type().is_file() || e.file_type().is_symlink())
-                        {
-                            if entry.file_type().is_symlink() {
-                                // 解析symlink
-                                let target = entry
-                                    .path()
-                                    .parent()
-                                    .unwrap()
-                                    .join(entry.path().read_link().unwrap());
-                                let metadata = target.metadata().unwrap();
-                                total_size += metadata.len();
-                                file_count += 1;
-                            } else if let Ok(metadata) = entry.metadata() {
-                                total_size += metadata.len();
-                                file_count += 1;
-                            }
                         }
-
-                        // 如果没有有效文件，跳过
-                        if file_count == 0 || total_size < 50 * 1024 * 1024 {
-                            continue;
+                    } else {
+                        None
+                    };
+
+                    // 回退到默认模板
+                    let effective_template =
+                        chat_template.unwrap_or_else(|| crate::template::get_default_template());
+
+                    // 统计模型文件总大小
+                    let mut total_size: u64 = 0;
+                    let mut file_count = 0;
+                    for entry in WalkDir::new(&snapshot_dir)
+                        .into_iter()
+                        .filter_map(Result::ok)
+                        .filter(|e| e.file_type().is_file() || e.file_type().is_symlink())
+                    {
+                        if entry.file_type().is_symlink() {
+                            // 解析symlink
+                            let target = entry
+                                .path()
+                                .parent()
+                                .unwrap()
+                                .join(entry.path().read_link().unwrap());
+                            let metadata = target.metadata().unwrap();
+                            total_size += metadata.len();
+                            file_count += 1;
+                        } else if let Ok(metadata) = entry.metadata() {
+                            total_size += metadata.len();
+                            file_count += 1;
                         }
+                    }
 
-                        // 创建模型条目
-                        let model = Model {
-                            format: ModelType::Transformers,
-                            path: snapshot_dir.clone(), // 指向 snapshot 目录
-                            name: model_name.clone(),
-                            size: total_size,
-                            template: Some(effective_template),
-                        };
-
-                        self.model_list.push(model);
+                    // 如果没有有效文件，跳过
+                    if file_count == 0 || total_size < 50 * 1024 * 1024 {
+                        continue;
                     }
+
+                    // 创建模型条目
+                    let model = Model {
+                        format: ModelType::Transformers,
+                        path: snapshot_dir.clone(), // 指向 snapshot 目录
+                        name: model_name.clone(),
+                        size: total_size,
+                        template: Some(effective_template),
+                        architecture,
+                        quant_hint: None,
+                        context_length,
+                        dtype,
+                    };
+
+                    models.push(model);
                 }
             }
         }
     }
+    models
+}
 
-    fn check_gguf_format(&self, path: &Path) -> bool {
-        if let Ok(mut file) = fs::File::open(path) {
-            let mut magic = [0u8; 4];
-            if let Ok(_) = file.read_exact(&mut magic) {
-                return &magic == b"GGUF";
-            }
-        }
-        false
+fn check_exclude(scan_all_paths: bool, path: &Path) -> bool {
+    if !scan_all_paths {
+        return false;
+    }
+    let uni_exclude_list = vec![".git", "node_modules", "venv", "__pycache__"];
+    #[cfg(target_os = "linux")]
+    let exclude_path = vec![
+        "/var", "/proc", "/run", "/sys", "/dev", "/lib", "/lib64", "/snap", "/boot",
+    ];
+    #[cfg(not(target_os = "linux"))]
+    let exclude_path = vec![
+        "C:\\Windows",
+        "C:\\Program Files",
+        "C:\\Program Files (x86)",
+        "C:\\ProgramData",
+    ];
+    if path
+        .components()
+        .filter_map(|c| c.as_os_str().to_str())
+        .any(|c_str| uni_exclude_list.contains(&c_str.to_lowercase().as_str()))
+    {
+        return true;
     }
 
-    fn check_safetensors_format(&self, path: &Path) -> bool {
-        if let Ok(mut file) = fs::File::open(path) {
-            // 读取元数据长度
-            let mut len_bytes = [0u8; 8];
-            if let Ok(_) = file.read_exact(&mut len_bytes) {
-                let len = u64::from_le_bytes(len_bytes) as usize;
-                if len > 50 * 1024 * 1024 {
-                    // 元数据长度不应超过 50MB
-                    return false;
-                }
-                // 读取元数据
-                let mut json_bytes = vec![0u8; len];
-                if let Ok(_) = file.read_exact(&mut json_bytes) {
-                    if let Ok(json_str) = String::from_utf8(json_bytes) {
-                        if let Ok(_) = serde_json::from_str::<Value>(&json_str) {
-                            // 检查是否包含 "metadata" 字段
-                            return true;
-                        }
-                    }
-                }
-            }
+    for excl in exclude_path {
+        if path.starts_with(excl) {
+            return true;
         }
-        false
     }
 
+    false
+}
+
+impl ModelDiscover {
     /// 构建一个包含所有潜在模型目录的列表
     pub fn make_search_paths(&self, check_existence: bool) -> Vec<PathBuf> {
         if self.scan_all_paths {
@@ -419,6 +879,12 @@ impl ModelDiscover {
                 paths.insert(PathBuf::from(trimmed_path));
             }
         }
+        for (base, _) in &self.include_rules {
+            paths.insert(base.clone());
+        }
+        for path in &self.config_search_paths {
+            paths.insert(path.clone());
+        }
 
         paths.insert(PathBuf::from("./models"));
         let home_dir = dirs::home_dir();
@@ -453,53 +919,6 @@ impl ModelDiscover {
             final_paths
         }
     }
-
-    fn check_exclude(&self, path: &Path) -> bool {
-        if !self.scan_all_paths {
-            return false;
-        }
-        let uni_exclude_list = vec![".git", "node_modules", "venv", "__pycache__"];
-        #[cfg(target_os = "linux")]
-        let exclude_path = vec![
-            "/var", "/proc", "/run", "/sys", "/dev", "/lib", "/lib64", "/snap", "/boot",
-        ];
-        #[cfg(not(target_os = "linux"))]
-        let exclude_path = vec![
-            "C:\\Windows",
-            "C:\\Program Files",
-            "C:\\Program Files (x86)",
-            "C:\\ProgramData",
-        ];
-        if path
-            .components()
-            .filter_map(|c| c.as_os_str().to_str()) // 转换为 &str
-            .any(|c_str| uni_exclude_list.contains(&c_str.to_lowercase().as_str()))
-        {
-            return true;
-        }
-
-        for excl in exclude_path {
-            if path.starts_with(excl) {
-                return true;
-            }
-        }
-
-        false
-    }
-
-    /// 获取发现的模型列表的只读引用 (无变化)
-    pub fn get_model_list(&self) -> &Vec<Model> {
-        &self.model_list
-    }
-
-    pub fn find_model(&self, model_name: &str) -> Result<Model, Box<dyn std::error::Error>> {
-        for model in &self.model_list {
-            if model.name == model_name {
-                return Ok(model.clone());
-            }
-        }
-        Err(format!("Model {} not found", model_name).into())
-    }
 }
 
 /// 检测目录是否拥有指定的特征
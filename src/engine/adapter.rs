@@ -27,14 +27,23 @@ impl InferenceEngine {
 
     pub fn infer(
         &self,
+        conversation_id: Option<&str>,
         prompt: &str,
         option: Option<&EngineConfig>,
         callback: Option<Box<dyn FnMut(String) + Send>>,
     ) -> Result<String> {
-        self.engine.infer(prompt, option, callback)
+        self.engine.infer(conversation_id, prompt, option, callback)
     }
 
     pub fn get_model_info(&self) -> Model {
         self.engine.get_model_info()
     }
+
+    pub fn count_tokens(&self, text: &str) -> usize {
+        self.engine.count_tokens(text)
+    }
+
+    pub fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        self.engine.embed(inputs)
+    }
 }
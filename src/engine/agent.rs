@@ -0,0 +1,150 @@
+//! 独立于 `ChatSession` 的多步 function-calling 执行器：给定「怎么从对话历史
+//! 拿到下一次模型回复」这一个回调和一份工具 handler 注册表，循环「推理 ->
+//! 解析工具调用 -> 执行 handler -> 把结果追加回历史」直到模型给出不带工具调用
+//! 的最终回复，或者到达步数上限。
+//!
+//! `ChatSession`（`chat.rs`）也用这个执行器跑多步工具调用；spinner、把每条
+//! 消息持久化到 `ConversationStore`、为检索增强建索引这些 CLI 特有的副作用
+//! 由 `ChatSession` 在执行器之外自己处理，不属于这个可复用的执行器。这个模块
+//! 提供的是不带这些副作用的最小核心，需要多步工具调用但不需要 `ChatSession`
+//! 那整套状态的调用方也可以直接复用。
+
+use crate::template::{Message, parse_tool_calls};
+use rayon::prelude::*;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// 工具 handler：接收 OpenAI 风格的 `arguments` JSON，返回要喂回模型的文本结果。
+/// 和 `tools::ToolHandler` 同样的用途，但错误类型是 `Box<dyn Error>` 而不是
+/// `String`，方便直接包装任意已有的 error 类型而不用先手动转成字符串。
+pub type AgentToolHandler = Box<dyn Fn(&Value) -> Result<String, Box<dyn Error>> + Send + Sync>;
+
+/// 一次 `Agent::run` 的结局：要么模型在步数上限内给出了最终回复，要么到达了
+/// 上限仍在请求工具调用。
+#[derive(Debug)]
+pub enum AgentOutcome {
+    /// 模型给出了不带工具调用的最终文本回复
+    Finished(String),
+    /// 到达 `max_steps` 仍未得到最终回复，调用方可以决定如何收尾
+    StepLimitReached,
+}
+
+/// 多步工具调用执行器，由 `AgentBuilder` 构造
+pub struct Agent {
+    handlers: HashMap<String, AgentToolHandler>,
+    max_steps: usize,
+}
+
+impl Agent {
+    pub fn builder() -> AgentBuilder {
+        AgentBuilder::new()
+    }
+
+    /// 跑完整个多步循环：每一步调用 `infer(history)` 拿到模型输出，解析其中的
+    /// 工具调用；没有工具调用就认为模型给出了最终回复，把它追加进 `history`
+    /// 并返回 `Finished`；有工具调用就按名称查 handler 执行，把结果追加成
+    /// `tool` 消息，继续下一步。未注册的工具名会产生一条描述错误的 `tool`
+    /// 消息而不是中断整个循环——模型经常能根据错误信息自己改正。
+    pub fn run(
+        &self,
+        history: &mut Vec<Message>,
+        mut infer: impl FnMut(&[Message]) -> Result<String, Box<dyn Error>>,
+    ) -> Result<AgentOutcome, Box<dyn Error>> {
+        for _ in 0..self.max_steps {
+            let result = infer(history)?;
+            let (leftover, tool_calls) = parse_tool_calls(&result);
+
+            if tool_calls.is_empty() {
+                history.push(Message {
+                    role: "assistant".to_string(),
+                    content: Some(result.clone().into()),
+                    tool_calls: None,
+                    name: None,
+                });
+                return Ok(AgentOutcome::Finished(result));
+            }
+
+            history.push(Message {
+                role: "assistant".to_string(),
+                content: leftover.map(Into::into),
+                tool_calls: Some(tool_calls.clone()),
+                name: None,
+            });
+
+            // 一轮里发起的多个工具调用之间互不依赖，并行执行；固定用一个按
+            // `TLLAMA_THREADS` 大小开的线程池（而不是 rayon 的全局默认池），避免
+            // 和其他也在用 rayon 的地方（比如 n/best_of 候选生成）抢同一份线程
+            // 预算。`.collect()` 保持和 `tool_calls` 一致的顺序，所以后面按顺序
+            // push 的 `tool` 消息和各自的调用对得上。
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(*crate::env::TLLAMA_THREADS as usize)
+                .build()
+                .expect("Failed to build tool-call thread pool");
+            let outputs: Vec<(String, String)> = pool.install(|| {
+                tool_calls
+                    .par_iter()
+                    .map(|call| {
+                        let arguments = call.function.arguments.clone().unwrap_or(Value::Null);
+                        let output = match self.handlers.get(&call.function.name) {
+                            Some(handler) => match handler(&arguments) {
+                                Ok(output) => output,
+                                Err(e) => format!("Error: {}", e),
+                            },
+                            None => format!("Error: unknown tool '{}'", call.function.name),
+                        };
+                        (call.function.name.clone(), output)
+                    })
+                    .collect()
+            });
+
+            for (name, output) in outputs {
+                history.push(Message {
+                    role: "tool".to_string(),
+                    content: Some(output.into()),
+                    tool_calls: None,
+                    name: Some(name),
+                });
+            }
+        }
+
+        Ok(AgentOutcome::StepLimitReached)
+    }
+}
+
+/// 构造一个 `Agent`：逐个注册工具 handler，按需覆盖默认的步数上限
+pub struct AgentBuilder {
+    handlers: HashMap<String, AgentToolHandler>,
+    max_steps: usize,
+}
+
+impl AgentBuilder {
+    fn new() -> Self {
+        AgentBuilder {
+            handlers: HashMap::new(),
+            max_steps: 8,
+        }
+    }
+
+    /// 注册一个按名称分派的工具 handler；同名注册会覆盖之前的
+    pub fn tool<F>(mut self, name: &str, handler: F) -> Self
+    where
+        F: Fn(&Value) -> Result<String, Box<dyn Error>> + Send + Sync + 'static,
+    {
+        self.handlers.insert(name.to_string(), Box::new(handler));
+        self
+    }
+
+    /// 覆盖默认的步数上限（默认 8）
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn build(self) -> Agent {
+        Agent {
+            handlers: self.handlers,
+            max_steps: self.max_steps,
+        }
+    }
+}
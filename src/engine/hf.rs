@@ -1,14 +1,17 @@
 use super::EngineBackend;
+use crate::config::APP_CONFIG;
 use crate::engine::EngineCallback;
 use crate::{discover::Model, engine::EngineConfig};
 use anyhow::Result;
 use lazy_static::lazy_static;
+use serde::Serialize;
 use serde_json::Value;
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::BufRead;
 use std::io::{BufReader, Write};
-use std::process::{ChildStdin, Command, Stdio};
+use std::process::{Child, ChildStdin, ChildStderr, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use tempfile::NamedTempFile;
@@ -16,12 +19,54 @@ use uuid::Uuid;
 
 // ========== 回调类型定义 ==========
 type ResponseCallback = Box<dyn FnMut(Value) + Send>;
+type SendersMap = Arc<Mutex<HashMap<String, ResponseCallback>>>;
+
+/// 守护进程崩溃后的重启策略：最多重试这么多次，每次重试前的等待时间按
+/// `RESTART_BACKOFF_BASE_MS * 2^(attempt-1)` 递增（`attempt` 从 1 开始计，
+/// 封顶在第 5 次翻倍）。
+const MAX_DAEMON_RESTARTS: usize = 5;
+const RESTART_BACKOFF_BASE_MS: u64 = 200;
+
+/// `PythonBackend::count_tokens` 等 daemon 回应 `tokenize` 请求的上限；数 token
+/// 只是为了 `Usage` 估计值，不值得为它无限期挂起调用方的线程。
+const TOKENIZE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// 暴露给 `/health` 之类端点的守护进程状态快照。
+#[derive(Debug, Clone, Serialize)]
+pub struct DaemonHealth {
+    pub alive: bool,
+    pub restart_count: usize,
+    pub loaded_models: Vec<String>,
+}
+
+/// `infer_with_callback` 的返回句柄：持有 daemon 一侧标识这次生成的
+/// `req_id`，调用方可以在客户端断开连接等场景下调用 [`InferHandle::cancel`]
+/// 主动中止这次生成，而不用等它按 `max_tokens` 跑完。
+pub struct InferHandle {
+    req_id: String,
+}
+
+impl InferHandle {
+    pub fn req_id(&self) -> &str {
+        &self.req_id
+    }
+
+    /// 中止这次生成。等价于 `PYTHON_BACKEND.cancel(self.req_id())`。
+    pub fn cancel(&self) -> Result<()> {
+        PYTHON_BACKEND.cancel(&self.req_id)
+    }
+}
 
 // ========== 全局单例 Python Backend ==========
+// `PythonBackend` 内部字段各自持有细粒度的锁（`stdin` 只在写请求时短暂加锁，
+// `response_senders` 只在注册/分发回调时短暂加锁），所以这里不再用一把外层
+// `Mutex` 包住整个实例——那会让每次 `infer` 从发请求到生成结束都独占整个
+// 后端，变成事实上的单请求串行，即使守护进程本身能用 `req_id` 交错处理
+// 多路请求也发挥不出来。
 lazy_static! {
-    pub static ref PYTHON_BACKEND: Mutex<PythonBackend> = {
+    pub static ref PYTHON_BACKEND: PythonBackend = {
         match PythonBackend::new() {
-            Ok(backend) => Mutex::new(backend),
+            Ok(backend) => backend,
             Err(e) => {
                 eprintln!("[FATAL] Can't start Python backend:");
                 eprintln!("错误: {}", e);
@@ -34,11 +79,55 @@ lazy_static! {
 // ========== PythonBackend 结构体 ==========
 pub struct PythonBackend {
     stdin: Arc<Mutex<ChildStdin>>,
-    response_senders: Arc<Mutex<HashMap<String, ResponseCallback>>>,
+    response_senders: SendersMap,
+    /// 当前在途的推理请求数，受 `max_in_flight_requests` 限制，
+    /// 只在 `infer_with_callback` 里增减（`load_model`/`unload_model` 不计入）。
+    in_flight: Arc<AtomicUsize>,
+    /// 崩溃前（以及重启后成功恢复的）已加载模型集合，供重启后重放 `load_model`。
+    loaded_models: Arc<Mutex<HashSet<String>>>,
+    /// 守护进程当前是否存活；在 supervisor 重启期间短暂为 `false`。
+    alive: Arc<AtomicBool>,
+    /// 自启动以来累计的重启次数。
+    restart_count: Arc<AtomicUsize>,
 }
 
 impl PythonBackend {
     pub fn new() -> Result<Self> {
+        let response_senders: SendersMap = Arc::new(Mutex::new(HashMap::new()));
+        let loaded_models = Arc::new(Mutex::new(HashSet::new()));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let alive = Arc::new(AtomicBool::new(false));
+        let restart_count = Arc::new(AtomicUsize::new(0));
+
+        let (child, stdin, stdout, stderr) = Self::spawn_process()?;
+        let stdin = Arc::new(Mutex::new(stdin));
+        alive.store(true, Ordering::SeqCst);
+
+        Self::supervise(
+            child,
+            stdout,
+            stderr,
+            Arc::clone(&stdin),
+            Arc::clone(&response_senders),
+            Arc::clone(&loaded_models),
+            Arc::clone(&in_flight),
+            Arc::clone(&alive),
+            Arc::clone(&restart_count),
+        );
+
+        Ok(PythonBackend {
+            stdin,
+            response_senders,
+            in_flight,
+            loaded_models,
+            alive,
+            restart_count,
+        })
+    }
+
+    /// 启动一份全新的 `hf_daemon.py` 子进程，返回句柄和三个管道端点。
+    /// 首次启动和崩溃后重启都走这一个函数，保证行为一致。
+    fn spawn_process() -> Result<(Child, ChildStdin, ChildStdout, ChildStderr)> {
         // 创建临时脚本文件
         let mut tmpfile = NamedTempFile::new()?;
         write!(tmpfile, "{}", include_str!("../assets/hf_daemon.py"))?;
@@ -56,10 +145,27 @@ impl PythonBackend {
                 anyhow::anyhow!("Failed to start Python process: {}. Make sure Python is installed and in PATH.", e)
             })?;
 
-        let stdin = Arc::new(Mutex::new(child.stdin.take().unwrap()));
+        let stdin = child.stdin.take().unwrap();
         let stdout = child.stdout.take().unwrap();
-        let stderr = child.stderr.take().unwrap(); // 获取 stderr
+        let stderr = child.stderr.take().unwrap();
+        Ok((child, stdin, stdout, stderr))
+    }
 
+    /// 给定一个已经启动的子进程，拉起 stderr/stdout 读取线程，以及一个在
+    /// 子进程退出时负责善后（唤醒所有等待中的调用方）并按退避策略尝试
+    /// 重启、恢复已加载模型的监控线程。
+    #[allow(clippy::too_many_arguments)]
+    fn supervise(
+        mut child: Child,
+        stdout: ChildStdout,
+        stderr: ChildStderr,
+        stdin_slot: Arc<Mutex<ChildStdin>>,
+        response_senders: SendersMap,
+        loaded_models: Arc<Mutex<HashSet<String>>>,
+        in_flight: Arc<AtomicUsize>,
+        alive: Arc<AtomicBool>,
+        restart_count: Arc<AtomicUsize>,
+    ) {
         // 启动 stderr 读取线程：实时输出 Python 错误信息
         thread::spawn(move || {
             let reader = BufReader::new(stderr);
@@ -71,12 +177,8 @@ impl PythonBackend {
             }
         });
 
-        // 共享的回调映射表
-        let response_senders: Arc<Mutex<HashMap<String, Box<dyn FnMut(Value) + Send + 'static>>>> =
-            Arc::new(Mutex::new(HashMap::new()));
-        let response_senders_clone = Arc::clone(&response_senders);
-
         // 启动读取线程：监听 Python 输出
+        let response_senders_clone = Arc::clone(&response_senders);
         thread::spawn(move || {
             let reader = BufReader::new(stdout);
             for line in reader.lines() {
@@ -114,58 +216,220 @@ impl PythonBackend {
             }
         });
 
-        // 启动等待线程：监控子进程退出
-        let response_senders_for_wait = Arc::clone(&response_senders);
+        // 启动监控线程：子进程退出后唤醒所有等待者，再按退避策略尝试重启
         thread::spawn(move || {
-            let status = match child.wait() {
-                Ok(s) => s,
+            let status = child.wait();
+            alive.store(false, Ordering::SeqCst);
+            match status {
+                Ok(status) if status.success() => {
+                    eprintln!("[PythonBackend] Python 进程正常退出");
+                }
+                Ok(status) => {
+                    eprintln!("[PythonBackend] Python 进程异常退出，状态: {}", status);
+                }
                 Err(e) => {
                     eprintln!("[PythonBackend] 等待子进程失败: {}", e);
+                }
+            }
+
+            // 让所有卡在 `recv()` 上的调用方立即解除阻塞，而不是永远等下去。
+            {
+                let mut senders = match response_senders.lock() {
+                    Ok(guard) => guard,
+                    Err(e) => e.into_inner(),
+                };
+                for (id, sender) in senders.iter_mut() {
+                    sender(json!({
+                        "req_id": id,
+                        "error": "python daemon exited unexpectedly",
+                        "done": true,
+                    }));
+                }
+                senders.clear();
+            }
+            in_flight.store(0, Ordering::SeqCst);
+
+            let attempt = restart_count.fetch_add(1, Ordering::SeqCst) + 1;
+            if attempt > MAX_DAEMON_RESTARTS {
+                eprintln!(
+                    "[PythonBackend] 已达到最大重启次数({})，守护进程保持离线状态，后续请求会直接报错而不是拖垮整个服务",
+                    MAX_DAEMON_RESTARTS
+                );
+                return;
+            }
+
+            let backoff_ms = RESTART_BACKOFF_BASE_MS * (1u64 << (attempt - 1).min(5));
+            eprintln!(
+                "[PythonBackend] {}ms 后进行第 {} 次重启尝试",
+                backoff_ms, attempt
+            );
+            thread::sleep(std::time::Duration::from_millis(backoff_ms));
+
+            let (new_child, new_stdin, new_stdout, new_stderr) = match Self::spawn_process() {
+                Ok(parts) => parts,
+                Err(e) => {
+                    eprintln!("[PythonBackend] 重启 Python 进程失败: {}", e);
                     return;
                 }
             };
 
-            if !status.success() {
-                eprintln!("[PythonBackend] Python 进程异常退出，状态: {}", status);
-                std::process::exit(1);
-            } else {
-                eprintln!("[PythonBackend] Python 进程正常退出");
+            {
+                let mut guard = match stdin_slot.lock() {
+                    Ok(guard) => guard,
+                    Err(e) => e.into_inner(),
+                };
+                *guard = new_stdin;
             }
+            alive.store(true, Ordering::SeqCst);
+
+            Self::supervise(
+                new_child,
+                new_stdout,
+                new_stderr,
+                Arc::clone(&stdin_slot),
+                Arc::clone(&response_senders),
+                Arc::clone(&loaded_models),
+                Arc::clone(&in_flight),
+                Arc::clone(&alive),
+                Arc::clone(&restart_count),
+            );
 
-            // 清理所有未完成的回调
-            let mut senders = match response_senders_for_wait.lock() {
-                Ok(guard) => guard,
-                Err(_) => return,
+            // 重启完成，重放崩溃前已加载的模型
+            let models_to_reload: Vec<String> = {
+                let guard = match loaded_models.lock() {
+                    Ok(guard) => guard,
+                    Err(e) => e.into_inner(),
+                };
+                guard.iter().cloned().collect()
             };
-            senders.clear();
+            for model in models_to_reload {
+                if let Err(e) =
+                    Self::send_command_and_wait(&stdin_slot, &response_senders, "load", &model, "loaded")
+                {
+                    eprintln!("[PythonBackend] 重启后恢复模型 '{}' 失败: {}", model, e);
+                }
+            }
         });
+    }
 
-        Ok(PythonBackend {
-            stdin,
-            response_senders,
-        })
+    /// `load_model`/`unload_model` 共用的请求-等待逻辑：发送一条 `cmd` 请求，
+    /// 阻塞直到 Python 侧回应 `done_key` 字段（或 `error`）。
+    fn send_command_and_wait(
+        stdin: &Arc<Mutex<ChildStdin>>,
+        response_senders: &SendersMap,
+        cmd: &str,
+        model: &str,
+        done_key: &str,
+    ) -> Result<()> {
+        let req_id = Uuid::new_v4().to_string();
+        let request = json!({
+            "req_id": req_id,
+            "cmd": cmd,
+            "model": model,
+        });
+
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let done_key = done_key.to_string();
+        {
+            let mut senders = response_senders
+                .lock()
+                .map_err(|e| anyhow::anyhow!("锁冲突: {:?}", e))?;
+            senders.insert(
+                req_id.clone(),
+                Box::new(move |json: Value| {
+                    if json.get(done_key.as_str()).is_some() || json.get("error").is_some() {
+                        let _ = tx.send(());
+                    }
+                }),
+            );
+        }
+
+        {
+            let mut stdin = stdin
+                .lock()
+                .map_err(|e| anyhow::anyhow!("stdin 锁失败: {:?}", e))?;
+            writeln!(stdin, "{}", serde_json::to_string(&request)?)?;
+            stdin.flush()?; // 关键：必须 flush
+        }
+
+        let _ = rx.recv();
+        Ok(())
     }
 
-    /// 发送推理请求并注册响应回调
+    /// 请求守护进程中止某个仍在生成中的请求。只是把 `cancel` 命令发给
+    /// Python 一侧，不等待确认——daemon 之后仍会按正常流程给该 `req_id`
+    /// 发一条 `done`，届时 `response_senders` 里注册的回调会自然清理、
+    /// `in_flight` 计数也会照常递减，不需要在这里单独收尾。
+    pub fn cancel(&self, req_id: &str) -> Result<()> {
+        let request = json!({
+            "cmd": "cancel",
+            "req_id": req_id,
+        });
+        let mut stdin = self
+            .stdin
+            .lock()
+            .map_err(|e| anyhow::anyhow!("stdin 锁失败: {:?}", e))?;
+        writeln!(stdin, "{}", serde_json::to_string(&request)?)?;
+        stdin.flush()?; // 关键：必须 flush
+        Ok(())
+    }
+
+    /// 当前守护进程状态快照，供 `/health` 之类的端点展示。
+    pub fn health(&self) -> DaemonHealth {
+        DaemonHealth {
+            alive: self.alive.load(Ordering::SeqCst),
+            restart_count: self.restart_count.load(Ordering::SeqCst),
+            loaded_models: self
+                .loaded_models
+                .lock()
+                .map(|guard| guard.iter().cloned().collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// 发送推理请求并注册响应回调。只在真正把请求写进 stdin 的那一小段
+    /// 持有 `stdin` 锁，写完立刻释放，不会因为等待生成结果而挡住其他请求。
     pub fn infer_with_callback<F>(
         &self,
         model_name: &str,
         prompt: &str,
         args: &EngineConfig,
-        callback: F,
-    ) -> Result<String>
+        mut callback: F,
+    ) -> Result<InferHandle>
     where
         F: FnMut(Value) + Send + 'static,
     {
+        if !self.alive.load(Ordering::SeqCst) {
+            return Err(anyhow::anyhow!("python daemon is not running"));
+        }
+
+        // 在真正发请求之前检查在途请求数上限，避免无限堆积生成中的会话占用内存。
+        if let Some(max_in_flight) = APP_CONFIG.max_in_flight_requests {
+            if self.in_flight.load(Ordering::SeqCst) >= max_in_flight {
+                return Err(anyhow::anyhow!(
+                    "too many in-flight requests (limit is {})",
+                    max_in_flight
+                ));
+            }
+        }
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+
         let req_id = Uuid::new_v4().to_string();
 
-        // 注册回调
+        // 注册回调：包一层，在生成结束（或出错）时把在途计数减回去。
         {
+            let in_flight = Arc::clone(&self.in_flight);
+            let wrapped = move |json: Value| {
+                if json.get("done").is_some() || json.get("error").is_some() {
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                }
+                callback(json);
+            };
             let mut senders = self
                 .response_senders
                 .lock()
                 .map_err(|e| anyhow::anyhow!("锁冲突: {:?}", e))?;
-            senders.insert(req_id.clone(), Box::new(callback));
+            senders.insert(req_id.clone(), Box::new(wrapped));
         }
 
         // 构造请求
@@ -186,22 +450,22 @@ impl PythonBackend {
             stdin.flush()?; // 关键：必须 flush
         }
 
-        Ok(req_id)
+        Ok(InferHandle { req_id })
     }
 
-    pub fn load_model(&self, model: &str) -> Result<()> {
+    /// 让守护进程用它自己加载的分词器数一遍 `text` 的 token 数，而不是在
+    /// Rust 这一侧按空白分词近似。daemon 需要对 `{"cmd": "tokenize", ...}`
+    /// 回一条带 `count` 和 `done` 字段的一次性响应。
+    pub fn count_tokens(&self, model: &str, text: &str) -> Result<usize> {
         let req_id = Uuid::new_v4().to_string();
         let request = json!({
             "req_id": req_id,
-            "cmd": "load",
+            "cmd": "tokenize",
             "model": model,
+            "text": text,
         });
 
-        // 创建同步信号
-        let loaded = Arc::new(Mutex::new(false));
-        let loaded_clone = Arc::clone(&loaded);
-
-        // 注册临时回调，等待加载完成
+        let (tx, rx) = std::sync::mpsc::channel::<Value>();
         {
             let mut senders = self
                 .response_senders
@@ -210,15 +474,11 @@ impl PythonBackend {
             senders.insert(
                 req_id.clone(),
                 Box::new(move |json: Value| {
-                    if json.get("loaded").is_some() || json.get("error").is_some() {
-                        let mut loaded = loaded_clone.lock().unwrap();
-                        *loaded = true;
-                    }
+                    let _ = tx.send(json);
                 }),
             );
         }
 
-        // 发送请求
         {
             let mut stdin = self
                 .stdin
@@ -228,66 +488,35 @@ impl PythonBackend {
             stdin.flush()?; // 关键：必须 flush
         }
 
-        // 等待加载完成
-        loop {
-            thread::sleep(std::time::Duration::from_millis(10));
-            let loaded = loaded.lock().unwrap();
-            if *loaded {
-                break;
-            }
+        // 限时等待：daemon 不在线（或者卡住了）时 `tokenize` 永远等不到回应，
+        // 用无超时的 `recv` 会把调用方的线程永久挂起。超时和显式错误响应一样
+        // 当失败处理，交给调用方（`TransformersEngine::count_tokens`）退化成
+        // 空白词数近似。
+        let response = rx
+            .recv_timeout(TOKENIZE_TIMEOUT)
+            .map_err(|_| anyhow::anyhow!("python daemon did not respond to tokenize request in time"))?;
+        if let Some(error) = response.get("error") {
+            return Err(anyhow::anyhow!("tokenize failed: {}", error));
         }
+        response["count"]
+            .as_u64()
+            .map(|count| count as usize)
+            .ok_or_else(|| anyhow::anyhow!("tokenize response missing 'count' field"))
+    }
 
+    pub fn load_model(&self, model: &str) -> Result<()> {
+        Self::send_command_and_wait(&self.stdin, &self.response_senders, "load", model, "loaded")?;
+        if let Ok(mut loaded) = self.loaded_models.lock() {
+            loaded.insert(model.to_string());
+        }
         Ok(())
     }
 
     pub fn unload_model(&self, model: &str) -> Result<()> {
-        let req_id = Uuid::new_v4().to_string();
-        let request = json!({
-            "req_id": req_id,
-            "cmd": "unload",
-            "model": model,
-        });
-
-        // 创建同步信号
-        let unloaded = Arc::new(Mutex::new(false));
-        let unloaded_clone = Arc::clone(&unloaded);
-
-        // 注册临时回调，等待卸载完成
-        {
-            let mut senders = self
-                .response_senders
-                .lock()
-                .map_err(|e| anyhow::anyhow!("锁冲突: {:?}", e))?;
-            senders.insert(
-                req_id.clone(),
-                Box::new(move |json: Value| {
-                    if json.get("unloaded").is_some() || json.get("error").is_some() {
-                        let mut unloaded = unloaded_clone.lock().unwrap();
-                        *unloaded = true;
-                    }
-                }),
-            );
-        }
-
-        // 发送请求
-        {
-            let mut stdin = self
-                .stdin
-                .lock()
-                .map_err(|e| anyhow::anyhow!("stdin 锁失败: {:?}", e))?;
-            writeln!(stdin, "{}", serde_json::to_string(&request)?)?;
-            stdin.flush()?; // 关键：必须 flush
+        Self::send_command_and_wait(&self.stdin, &self.response_senders, "unload", model, "unloaded")?;
+        if let Ok(mut loaded) = self.loaded_models.lock() {
+            loaded.remove(model);
         }
-
-        // 等待卸载完成
-        loop {
-            thread::sleep(std::time::Duration::from_millis(10));
-            let unloaded = unloaded.lock().unwrap();
-            if *unloaded {
-                break;
-            }
-        }
-
         Ok(())
     }
 }
@@ -318,8 +547,7 @@ pub struct TransformersEngine {
 
 impl EngineBackend for TransformersEngine {
     fn new(args: &EngineConfig, model_info: &Model) -> Result<Self> {
-        let backend = PYTHON_BACKEND.lock().expect("锁被污染");
-        backend.load_model(model_info.path.to_str().unwrap())?;
+        PYTHON_BACKEND.load_model(model_info.path.to_str().unwrap())?;
         Ok(Self {
             model_info: model_info.clone(),
             args: args.clone(),
@@ -328,6 +556,8 @@ impl EngineBackend for TransformersEngine {
 
     fn infer(
         &self,
+        // Python 守护进程协议目前不支持保留 KV cache 会话，这里忽略该参数。
+        _conversation_id: Option<&str>,
         prompt: &str,
         option: Option<&EngineConfig>,
         callback: Option<EngineCallback>,
@@ -339,64 +569,83 @@ impl EngineBackend for TransformersEngine {
             .to_str()
             .ok_or_else(|| anyhow::anyhow!("模型路径包含非 UTF-8 字符"))?;
 
-        // 获取全局 backend
-        let backend = PYTHON_BACKEND
-            .lock()
-            .map_err(|e| anyhow::anyhow!("PythonBackend 锁被污染: {:?}", e))?;
-
-        // 创建同步信号
-        let finished = Arc::new(Mutex::new(false));
-        let finished_clone = Arc::clone(&finished);
+        // 用一个一次性 channel 代替"轮询 Mutex<bool>"：生成结束时回调往里发一个
+        // 信号，这里直接阻塞等待那个信号，既不busy-wait，也不需要独占整个 backend。
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
 
         // 将 callback 包装为 Arc<Mutex<Option<...>>>，以便在闭包中多次使用
         let shared_callback: Arc<Mutex<Option<EngineCallback>>> = Arc::new(Mutex::new(callback));
 
+        // `infer_with_callback` 在真正拿到 req_id 之前闭包就已经被传进去了，
+        // 所以这里先放一个空位，等请求发出去、拿到 `InferHandle` 后再填上，
+        // 这样回调一旦看到调用方要求中止，就能把它转发成对 daemon 的真实 cancel。
+        let req_id_cell: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+
         // 创建闭包，适配 PythonBackend 的 FnMut(Value) 接口
         let closure_callback = {
             let shared_callback = Arc::clone(&shared_callback);
-            let finished_clone = Arc::clone(&finished_clone);
+            let req_id_cell = Arc::clone(&req_id_cell);
             move |json: Value| {
                 // 检查是否完成
                 if json.get("done").is_some() || json.get("error").is_some() {
-                    let mut finished = finished_clone.lock().unwrap();
-                    *finished = true;
+                    let _ = done_tx.send(());
                     return;
                 }
 
                 let token = json["token"].as_str().unwrap_or_default();
-                let mut guard = shared_callback.lock().unwrap();
-                if let Some(ref mut cb) = *guard {
-                    cb(token.to_string());
+                let should_cancel = {
+                    let mut guard = shared_callback.lock().unwrap();
+                    match *guard {
+                        Some(ref mut cb) => cb(token.to_string()),
+                        None => false,
+                    }
+                };
+                // 回调返回 true 说明调用方（比如检测到 HTTP 客户端断开的那一层）
+                // 要求中止生成，把它转发成一条真正发给 daemon 的 cancel 命令，
+                // 而不是只在 Rust 这一侧停止转发 token、让后台白白算下去。
+                if should_cancel {
+                    if let Some(req_id) = req_id_cell.lock().unwrap().clone() {
+                        let _ = PYTHON_BACKEND.cancel(&req_id);
+                    }
                 }
             }
         };
 
         // 发送请求并注册回调
-        let req_id = backend.infer_with_callback(model_path, prompt, args, closure_callback)?;
-
-        // 等待生成完成
-        loop {
-            thread::sleep(std::time::Duration::from_millis(10));
-            let finished = finished.lock().unwrap();
-            if *finished {
-                break;
-            }
-        }
+        let handle = PYTHON_BACKEND.infer_with_callback(model_path, prompt, args, closure_callback)?;
+        *req_id_cell.lock().unwrap() = Some(handle.req_id().to_string());
+
+        // 阻塞等待生成完成信号，而不是轮询
+        let _ = done_rx.recv();
 
-        Ok(req_id)
+        Ok(handle.req_id().to_string())
     }
 
     fn get_model_info(&self) -> Model {
         self.model_info.clone()
     }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        let Some(model_path) = self.model_info.path.to_str() else {
+            return text.split_whitespace().count();
+        };
+        // 优先让守护进程用它实际加载的分词器数一遍；daemon 不在线或请求失败
+        // 时退化成粗略的空白词数近似，保证 `Usage` 里至少有个非零的估计值。
+        PYTHON_BACKEND
+            .count_tokens(model_path, text)
+            .unwrap_or_else(|_| text.split_whitespace().count())
+    }
+
+    fn embed(&self, _inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        // Python 守护进程协议目前只支持生成式推理，尚未暴露 embeddings 接口
+        Err(anyhow::anyhow!(
+            "TransformersEngine does not support embeddings yet"
+        ))
+    }
 }
 
 impl Drop for TransformersEngine {
     fn drop(&mut self) {
-        let backend = PYTHON_BACKEND
-            .lock()
-            .map_err(|e| anyhow::anyhow!("PythonBackend 锁被污染: {:?}", e))
-            .unwrap();
-        let _ = backend.unload_model(self.model_info.path.to_str().unwrap());
+        let _ = PYTHON_BACKEND.unload_model(self.model_info.path.to_str().unwrap());
     }
 }
@@ -2,25 +2,47 @@ use crate::discover::Model;
 use crate::engine::{EngineBackend, EngineCallback, EngineConfig};
 use anyhow::Result;
 use lazy_static::lazy_static;
-use llama_cpp_2::context::params::LlamaContextParams;
+use llama_cpp_2::context::LlamaContext;
+use llama_cpp_2::context::params::{LlamaContextParams, LlamaPoolingType};
 use llama_cpp_2::llama_backend::LlamaBackend;
 use llama_cpp_2::llama_batch::LlamaBatch;
 use llama_cpp_2::model::params::LlamaModelParams;
 use llama_cpp_2::model::{AddBos, LlamaModel, Special};
 use llama_cpp_2::sampling::LlamaSampler;
+use llama_cpp_2::token::LlamaToken;
+use std::collections::HashMap;
 use std::num::NonZeroU32;
+use std::sync::Mutex;
 
 lazy_static! {
     pub static ref LLAMA_BACKEND: LlamaBackend = LlamaBackend::init().unwrap();
 }
 
-// 声明LlamaEngine是线程安全的
+// 声明LlamaEngine是线程安全的：`sessions` 里的 `LlamaContext<'static>` 其实是
+// 从下面的 `model` 借用出来的（见字段顺序和 `fresh_session` 里的安全性说明），
+// `llama_cpp_2` 的类型本身没有实现 Send/Sync，这里手动保证跨线程访问时不会
+// 出现数据竞争。
 unsafe impl Send for LlamaEngine {}
 unsafe impl Sync for LlamaEngine {}
 
+/// 某个对话在这个模型上保留的 KV cache 会话：记录已经喂给模型的 token 序列
+/// （prompt + 之前轮次生成的回复）和当前的 `n_cur`，下一轮只需要
+/// tokenize + decode 新增的那部分 prompt，而不用把整段历史重新喂一遍。
+struct LlamaSession {
+    ctx: LlamaContext<'static>,
+    tokens: Vec<LlamaToken>,
+    n_cur: i32,
+}
+
 pub struct LlamaEngine {
+    /// 按 conversation id 保留的 KV cache 会话。`LlamaSession::ctx` 借用了
+    /// 下面的 `model`，必须先于它被析构，所以这个字段要声明在 `model` 之
+    /// 前——Rust 按字段声明顺序析构结构体字段。
+    sessions: Mutex<HashMap<String, LlamaSession>>,
     model_info: Model,
-    model: LlamaModel,
+    /// 装箱以获得一个不随 `LlamaEngine` 本身被移动而改变的稳定堆地址，这样
+    /// `sessions` 里借用它的 `'static` 上下文才是安全的。
+    model: Box<LlamaModel>,
     args: EngineConfig,
 }
 
@@ -30,7 +52,8 @@ impl EngineBackend for LlamaEngine {
         let model = LlamaModel::load_from_file(&LLAMA_BACKEND, &model_info.path, &model_params)?;
 
         Ok(LlamaEngine {
-            model,
+            sessions: Mutex::new(HashMap::new()),
+            model: Box::new(model),
             args: (*args).clone(),
             model_info: model_info.clone(),
         })
@@ -39,8 +62,16 @@ impl EngineBackend for LlamaEngine {
         self.model_info.clone()
     }
 
+    fn count_tokens(&self, text: &str) -> usize {
+        self.model
+            .str_to_token(text, AddBos::Never)
+            .map(|tokens| tokens.len())
+            .unwrap_or(0)
+    }
+
     fn infer(
         &self,
+        conversation_id: Option<&str>,
         prompt: &str,
         args: Option<&EngineConfig>,
         mut callback: Option<EngineCallback>,
@@ -48,43 +79,78 @@ impl EngineBackend for LlamaEngine {
         // 获取EngineConfig实例
         let args = args.unwrap_or(&self.args);
         let mut decoder = encoding_rs::UTF_8.new_decoder();
-        // 设置上下文参数
-        let ctx_params = LlamaContextParams::default()
-            .with_n_ctx(Some(NonZeroU32::new(args.n_ctx as u32).unwrap()))
-            .with_n_batch(2048)
-            .with_n_ubatch(512)
-            .with_n_threads(*crate::env::TLLAMA_THREADS)
-            .with_n_threads_batch(*crate::env::TLLAMA_THREADS)
-            .with_flash_attention(*crate::env::TLLAMA_FLASH_ATTN);
-        // 创建上下文
-        let mut ctx = self.model.new_context(&LLAMA_BACKEND, ctx_params)?;
         // Tokenize提示
-        let tokens_list = self.model.str_to_token(&prompt, AddBos::Always)?;
-        // 创建初始batch
-        let mut batch = LlamaBatch::new(tokens_list.len(), 1);
-        for (i, &token) in tokens_list.iter().enumerate() {
-            let logits = i == tokens_list.len() - 1;
-            batch.add(token, i as i32, &[0], logits)?;
+        let tokens_list = self.model.str_to_token(prompt, AddBos::Always)?;
+
+        // 看看这个对话有没有一个还能复用的 KV cache 会话：先把它从表里取出来
+        // （而不是只借用），这样下面要么原地继续用它，要么让它在作用域结束
+        // 时被直接丢弃换成新开的上下文。锁只在这一次 `remove` 期间持有——
+        // 生成过程可能跑很久，如果锁一直拿到最后才放，不同 `conversation_id`
+        // 甚至完全没有 `conversation_id` 的并发请求都会被迫排队等这一个
+        // `infer` 跑完，n/best_of 那样的并发候选生成也就名存实亡了。
+        let cached = conversation_id.and_then(|id| self.sessions.lock().unwrap().remove(id));
+
+        let mut session = match cached {
+            Some(session) if Self::can_reuse(&session, &tokens_list, args.n_ctx) => session,
+            // 没有缓存、已保留的前缀和新 prompt 对不上（说明对话被编辑或者
+            // 分叉了），或者剩下的上下文窗口装不下——回退到全新上下文，
+            // 重新 decode 一遍完整的 prompt。
+            _ => self.fresh_session(&tokens_list, args.n_ctx)?,
+        };
+
+        // 只 decode 这一轮相对于已缓存前缀新增的那部分 token
+        if session.tokens.len() < tokens_list.len() {
+            let new_tokens = &tokens_list[session.tokens.len()..];
+            let mut batch = LlamaBatch::new(new_tokens.len(), 1);
+            for (i, &token) in new_tokens.iter().enumerate() {
+                let logits = i == new_tokens.len() - 1;
+                batch.add(token, session.n_cur + i as i32, &[0], logits)?;
+            }
+            session.ctx.decode(&mut batch)?;
+            session.n_cur += new_tokens.len() as i32;
+            session.tokens.extend_from_slice(new_tokens);
         }
-        // 解码初始提示
-        ctx.decode(&mut batch)?;
 
-        let mut sampler = LlamaSampler::chain_simple([
+        let seed = args.seed.unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .subsec_nanos()
+        });
+        // 重复惩罚要看到完整的已保留 token 历史（prompt + 之前轮次的回复），
+        // 而不只是这一轮新追加的部分，所以用 `session.tokens` 播种采样器。
+        let mut chain_stages = vec![
             LlamaSampler::temp(args.temperature),
-            LlamaSampler::top_p(args.top_p, 1),
             LlamaSampler::top_k(args.top_k),
-            LlamaSampler::penalties(64, args.repeat_penalty, 0.0, 0.0),
-            LlamaSampler::greedy(),
-        ])
-        .with_tokens(tokens_list.iter().copied());
-        let mut n_cur = batch.n_tokens();
+            LlamaSampler::typical_p(args.typical_p, 1),
+            LlamaSampler::top_p(args.top_p, 1),
+            LlamaSampler::min_p(args.min_p, 1),
+            LlamaSampler::penalties(
+                args.repeat_last_n,
+                args.repeat_penalty,
+                args.frequency_penalty,
+                args.presence_penalty,
+            ),
+        ];
+        if !args.logit_bias.is_empty() {
+            let biases: Vec<(LlamaToken, f32)> = args
+                .logit_bias
+                .iter()
+                .map(|(&token_id, &bias)| (LlamaToken::new(token_id), bias))
+                .collect();
+            chain_stages.push(LlamaSampler::logit_bias(self.model.n_vocab(), biases));
+        }
+        chain_stages.push(LlamaSampler::dist(seed));
+        let mut sampler =
+            LlamaSampler::chain_simple(chain_stages).with_tokens(session.tokens.iter().copied());
         let mut n_decode = 0;
         let mut output = String::new();
 
         let max_tokens = args.n_len.map(|n| n as i32);
+        let mut gen_batch = LlamaBatch::new(1, 1);
         while max_tokens.map_or(true, |max| n_decode < max) {
             // 采样下一个token
-            let token = sampler.sample(&ctx, -1);
+            let token = sampler.sample(&session.ctx, -1);
             // 检查是否是EOS
             if self.model.is_eog_token(token) {
                 break;
@@ -96,6 +162,30 @@ impl EngineBackend for LlamaEngine {
             let mut token_str = String::with_capacity(32);
             let _decode_result = decoder.decode_to_string(&output_bytes, &mut token_str, false);
 
+            // 命中某个 stop 序列：只把匹配起点之前、还没发出去的那部分转发给
+            // 回调，把输出截断到匹配起点，然后结束生成——停止词本身不出现在
+            // 最终返回的文本里。逐个 token 检查能保证跨 token 的匹配在它最后
+            // 一段到来的这一轮被发现，不会漏判；但停止词跨 token 时，落在之前
+            // 各轮 token 里的那部分已经交给过回调，流式输出不会撤回这部分。
+            let prefix_len = output.len();
+            let combined = format!("{}{}", output, token_str);
+            let stop_pos = args
+                .stop
+                .iter()
+                .filter(|s| !s.is_empty())
+                .filter_map(|s| combined.find(s.as_str()))
+                .min();
+            if let Some(stop_pos) = stop_pos {
+                let remainder = combined.get(prefix_len..stop_pos).unwrap_or("");
+                if !remainder.is_empty() {
+                    if let Some(cb) = callback.as_mut() {
+                        cb(remainder.to_string());
+                    }
+                }
+                output = combined[..stop_pos].to_string();
+                break;
+            }
+
             // 调用回调函数处理输出
             if callback.is_some() {
                 let shoud_stop = callback.as_mut().unwrap()(token_str.clone());
@@ -107,14 +197,109 @@ impl EngineBackend for LlamaEngine {
             // 将新生成的token添加到采样器历史中
             sampler.accept(token);
             // 清空批次并添加新生成的token
-            batch.clear();
-            batch.add(token, n_cur as i32, &[0], true)?;
-            n_cur += 1;
+            gen_batch.clear();
+            gen_batch.add(token, session.n_cur, &[0], true)?;
+            session.n_cur += 1;
+            session.tokens.push(token);
             n_decode += 1;
             output += &token_str;
             // 解码新批次
-            ctx.decode(&mut batch)?;
+            session.ctx.decode(&mut gen_batch)?;
+
+            // 上下文窗口快用满了就停止生成；下一轮 `can_reuse` 会发现剩余
+            // 空间装不下新 prompt，自动回退到全新上下文。
+            if session.n_cur as u32 >= args.n_ctx as u32 {
+                break;
+            }
         }
+
+        if let Some(id) = conversation_id {
+            self.sessions.lock().unwrap().insert(id.to_string(), session);
+        }
+
         Ok(output)
     }
+
+    fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>> {
+        // 创建一次支持 embeddings 的上下文，使用 mean pooling 得到单个句向量；
+        // 一个请求里的多个输入共用同一个上下文，省去逐条重建上下文的开销。
+        let ctx_params = LlamaContextParams::default()
+            .with_n_ctx(Some(NonZeroU32::new(self.args.n_ctx as u32).unwrap()))
+            .with_n_threads(*crate::env::TLLAMA_THREADS)
+            .with_n_threads_batch(*crate::env::TLLAMA_THREADS)
+            .with_embeddings(true)
+            .with_pooling_type(LlamaPoolingType::Mean);
+        let mut ctx = self.model.new_context(&LLAMA_BACKEND, ctx_params)?;
+
+        let mut embeddings = Vec::with_capacity(inputs.len());
+        for text in inputs {
+            let tokens_list = self.model.str_to_token(text, AddBos::Always)?;
+            let mut batch = LlamaBatch::new(tokens_list.len(), 1);
+            for (i, &token) in tokens_list.iter().enumerate() {
+                // 池化依赖完整序列，每个 token 都要产出 logits/embedding
+                batch.add(token, i as i32, &[0], true)?;
+            }
+            ctx.clear_kv_cache();
+            ctx.decode(&mut batch)?;
+
+            let embedding = ctx.embeddings_seq_ith(0)?;
+            embeddings.push(normalize(embedding));
+        }
+        Ok(embeddings)
+    }
+}
+
+impl LlamaEngine {
+    fn context_params(n_ctx: i32) -> LlamaContextParams {
+        LlamaContextParams::default()
+            .with_n_ctx(Some(NonZeroU32::new(n_ctx as u32).unwrap()))
+            .with_n_batch(2048)
+            .with_n_ubatch(512)
+            .with_n_threads(*crate::env::TLLAMA_THREADS)
+            .with_n_threads_batch(*crate::env::TLLAMA_THREADS)
+            .with_flash_attention(*crate::env::TLLAMA_FLASH_ATTN)
+    }
+
+    /// 判断缓存的 session 能否直接复用：它已经 decode 过的 token 序列必须是
+    /// 新 prompt 的前缀（否则说明对话被编辑或分叉了，缓存已经失效），并且
+    /// 剩余的上下文窗口还能放下新 prompt。
+    fn can_reuse(session: &LlamaSession, tokens_list: &[LlamaToken], n_ctx: i32) -> bool {
+        session.tokens.len() <= tokens_list.len()
+            && tokens_list[..session.tokens.len()] == session.tokens[..]
+            && (tokens_list.len() as u32) < n_ctx as u32
+    }
+
+    /// 为一次全新的推理打开上下文：decode 整段 prompt，返回携带已解码 token
+    /// 的 session。
+    fn fresh_session(&self, tokens: &[LlamaToken], n_ctx: i32) -> Result<LlamaSession> {
+        let ctx_params = Self::context_params(n_ctx);
+        let ctx = self.model.new_context(&LLAMA_BACKEND, ctx_params)?;
+        // Safety: `ctx` 借用自 `self.model`，而 `model` 装箱存放在堆上、
+        // 地址稳定，并且按字段声明顺序晚于 `sessions`（见 `LlamaEngine`
+        // 定义），所以 `sessions` 总会先于 `model` 被析构，这里把生命周期
+        // 延长到 `'static` 不会产生悬垂引用。
+        let mut ctx: LlamaContext<'static> = unsafe { std::mem::transmute(ctx) };
+
+        let mut batch = LlamaBatch::new(tokens.len(), 1);
+        for (i, &token) in tokens.iter().enumerate() {
+            let logits = i == tokens.len() - 1;
+            batch.add(token, i as i32, &[0], logits)?;
+        }
+        ctx.decode(&mut batch)?;
+
+        Ok(LlamaSession {
+            ctx,
+            tokens: tokens.to_vec(),
+            n_cur: tokens.len() as i32,
+        })
+    }
+}
+
+/// 将向量归一化为单位长度，方便下游直接做点积/余弦距离比较
+fn normalize(vector: &[f32]) -> Vec<f32> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return vector.to_vec();
+    }
+    vector.iter().map(|v| v / norm).collect()
 }
@@ -1,19 +1,31 @@
 use crate::discover::Model;
 use anyhow::Result;
+use std::collections::HashMap;
 
-type EngineCallback = Box<dyn FnMut(String) -> bool + Send>;
+pub type EngineCallback = Box<dyn FnMut(String) -> bool + Send>;
 
 pub trait EngineBackend: Send + Sync {
     fn new(args: &EngineConfig, model: &Model) -> Result<Self>
     where
         Self: Sized;
+    /// `conversation_id`、非 `None` 时，实现可以把这次推理和某个 KV cache 会话
+    /// 关联起来，在后续调用里跳过已经 decode 过的那部分 prompt；传 `None`
+    /// 的调用方明确表示这是一次一次性的、不需要跨轮复用上下文的推理。
     fn infer(
         &self,
+        conversation_id: Option<&str>,
         prompt: &str,
         option: Option<&EngineConfig>,
         callback: Option<EngineCallback>,
     ) -> Result<String>;
     fn get_model_info(&self) -> Model;
+    /// Counts how many tokens `text` would occupy for this model, for usage accounting.
+    fn count_tokens(&self, text: &str) -> usize;
+    /// Embeds each of `inputs` into a normalized sentence vector, for use with
+    /// `VectorStore` and the `/v1/embeddings` API. Batched so implementations can
+    /// amortize the cost of standing up an embedding-mode context across the whole
+    /// request instead of paying it once per input.
+    fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>>;
 }
 
 #[macro_export]
@@ -31,6 +43,27 @@ pub struct EngineConfig {
     pub top_k: i32,
     pub top_p: f32,
     pub repeat_penalty: f32,
+    /// Min-p sampling threshold; 0.0 disables it.
+    pub min_p: f32,
+    /// Locally typical sampling mass; 1.0 disables it.
+    pub typical_p: f32,
+    /// RNG seed for sampling; `None` picks a random seed per inference.
+    pub seed: Option<u32>,
+    /// Number of recent tokens considered by the repeat penalty.
+    pub repeat_last_n: i32,
+    /// Strings that, once emitted, truncate the output at their start and end
+    /// generation. Checked against the rolling output on every emitted token.
+    #[serde(default)]
+    pub stop: Vec<String>,
+    /// Flat per-token penalty applied to every token already sampled at least once.
+    #[serde(default)]
+    pub presence_penalty: f32,
+    /// Per-token penalty that scales with how many times a token has already been sampled.
+    #[serde(default)]
+    pub frequency_penalty: f32,
+    /// Additive logit bias keyed by token id, OpenAI `logit_bias` semantics.
+    #[serde(default)]
+    pub logit_bias: HashMap<i32, f32>,
 }
 
 impl Default for EngineConfig {
@@ -42,11 +75,20 @@ impl Default for EngineConfig {
             top_k: 40,
             top_p: 0.9,
             repeat_penalty: 1.1,
+            min_p: 0.0,
+            typical_p: 1.0,
+            seed: None,
+            repeat_last_n: 64,
+            stop: Vec::new(),
+            presence_penalty: 0.0,
+            frequency_penalty: 0.0,
+            logit_bias: HashMap::new(),
         }
     }
 }
 
 pub mod adapter;
+pub mod agent;
 
 pub use adapter::InferenceEngine;
 use serde::Serialize;
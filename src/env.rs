@@ -1,4 +1,5 @@
 use lazy_static::lazy_static;
+use std::path::PathBuf;
 
 #[cfg(feature = "llama-cpp-2")]
 use llama_cpp_2::context::params::KvCacheType;
@@ -14,6 +15,51 @@ lazy_static! {
     pub static ref TLLAMA_MODEL_PATHS: Vec<String> = std::env::var("TLLAMA_MODEL_PATHS")
         .map(|s| { s.split(",").map(|s| s.to_string()).collect() })
         .unwrap_or(vec![]);
+    /// 模型发现阶段使用的 Rayon 线程数，默认等于 CPU 核心数
+    pub static ref TLLAMA_DISCOVER_THREADS: i32 = std::env::var("TLLAMA_DISCOVER_THREADS")
+        .map(|s| s.parse::<i32>().unwrap())
+        .unwrap_or(
+            std::thread::available_parallelism()
+                .map(|n| n.get() as i32)
+                .unwrap_or(4)
+        );
+    /// 逗号分隔的模型发现 include glob 规则，例如 `~/ml/**/*.gguf`
+    pub static ref TLLAMA_INCLUDE_GLOBS: Vec<String> = std::env::var("TLLAMA_INCLUDE_GLOBS")
+        .map(|s| {
+            s.split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    /// 逗号分隔的模型发现 exclude glob 规则，例如 `**/node_modules/**`
+    pub static ref TLLAMA_EXCLUDE_GLOBS: Vec<String> = std::env::var("TLLAMA_EXCLUDE_GLOBS")
+        .map(|s| {
+            s.split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    /// 分层发现配置文件路径，默认为用户配置目录下的 `tllama/tllama.conf`
+    pub static ref TLLAMA_CONFIG_PATH: Option<PathBuf> = std::env::var("TLLAMA_CONFIG_PATH")
+        .ok()
+        .map(PathBuf::from)
+        .or_else(|| dirs::config_dir().map(|c| c.join("tllama").join("tllama.conf")));
+    /// 全局推理配置文件路径（JSON 或 TOML，按扩展名判断），默认为用户配置目录下的
+    /// `tllama/engine.toml`。文件若不存在则直接使用内置默认值，不视为错误。
+    pub static ref TLLAMA_ENGINE_CONFIG_PATH: Option<PathBuf> =
+        std::env::var("TLLAMA_ENGINE_CONFIG_PATH")
+            .ok()
+            .map(PathBuf::from)
+            .or_else(|| dirs::config_dir().map(|c| c.join("tllama").join("engine.toml")));
+    /// 对话持久化使用的 SQLite 文件路径，默认为用户配置目录下的
+    /// `tllama/conversations.sqlite3`
+    pub static ref TLLAMA_CONVERSATION_STORE_PATH: Option<PathBuf> =
+        std::env::var("TLLAMA_CONVERSATION_STORE_PATH")
+            .ok()
+            .map(PathBuf::from)
+            .or_else(|| dirs::config_dir().map(|c| c.join("tllama").join("conversations.sqlite3")));
 
     #[cfg(feature = "llama-cpp-2")]
     pub static ref TLLAMA_FLASH_ATTN: i32 = std::env::var("TLLAMA_FLASH_ATTN")
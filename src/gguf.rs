@@ -0,0 +1,222 @@
+//! 轻量级 GGUF 头部解析器：只读取文件发现关心的少量标量/字符串键，
+//! 从不缓冲张量数据，遇到数组一律按长度跳过。
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+/// KV/数组条目数量上限，防止损坏文件诱导我们分配天文数字大小的缓冲区
+const MAX_ENTRY_COUNT: u64 = 1_000_000;
+/// 单个字符串长度上限（足够容纳最长的 chat template）
+const MAX_STRING_LEN: u64 = 16 * 1024 * 1024;
+
+/// 从 GGUF 头部提取出的、对模型发现有用的元数据子集
+#[derive(Debug, Clone, Default)]
+pub struct GgufMetadata {
+    pub architecture: Option<String>,
+    pub name: Option<String>,
+    /// 从 `general.file_type` 粗略推断出的量化类型提示（如 "Q4_K_M"）
+    pub quant_hint: Option<String>,
+    pub context_length: Option<u32>,
+    pub chat_template: Option<String>,
+}
+
+/// 只确认文件以 GGUF 魔数开头，不解析头部其余部分
+pub fn is_gguf_file(path: &Path) -> bool {
+    let Ok(mut file) = File::open(path) else {
+        return false;
+    };
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic).is_ok() && &magic == GGUF_MAGIC
+}
+
+/// 解析 GGUF 头部的 key-value 元数据区。
+///
+/// 返回 `None` 表示这根本不是一个 GGUF 文件（魔数不匹配）；
+/// 返回 `Some` 则代表"已确认是 GGUF"，即便内部解析中途失败，
+/// 也只是退化为目前已经收集到的字段（保留原本"仅凭魔数"的判定结果），
+/// 而不会把一个已经确认的 GGUF 文件错误地判定为不是模型。
+pub fn read_gguf_metadata(path: &Path) -> Option<GgufMetadata> {
+    let mut file = File::open(path).ok()?;
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic).ok()?;
+    if &magic != GGUF_MAGIC {
+        return None;
+    }
+
+    let mut metadata = GgufMetadata::default();
+
+    // 版本号与张量数量目前不使用，但必须消费掉这些字节才能继续解析后面的 KV 区
+    let (Some(_version), Some(_tensor_count), Some(kv_count)) =
+        (read_u32(&mut file), read_u64(&mut file), read_u64(&mut file))
+    else {
+        return Some(metadata);
+    };
+    if kv_count > MAX_ENTRY_COUNT {
+        return Some(metadata);
+    }
+
+    for _ in 0..kv_count {
+        let Some(key) = read_gguf_string(&mut file) else {
+            return Some(metadata);
+        };
+        let Some(value_type) = read_u32(&mut file) else {
+            return Some(metadata);
+        };
+
+        let is_context_length = key.ends_with(".context_length");
+        let wants_value = matches!(
+            key.as_str(),
+            "general.architecture" | "general.name" | "general.file_type" | "tokenizer.chat_template"
+        ) || is_context_length;
+
+        if !wants_value {
+            if skip_gguf_value(&mut file, value_type).is_none() {
+                return Some(metadata);
+            }
+            continue;
+        }
+
+        let Some(value) = read_gguf_value(&mut file, value_type) else {
+            return Some(metadata);
+        };
+
+        match (key.as_str(), value) {
+            ("general.architecture", GgufValue::Str(v)) => metadata.architecture = Some(v),
+            ("general.name", GgufValue::Str(v)) => metadata.name = Some(v),
+            ("tokenizer.chat_template", GgufValue::Str(v)) => metadata.chat_template = Some(v),
+            ("general.file_type", GgufValue::UInt(v)) => metadata.quant_hint = Some(file_type_name(v)),
+            (_, GgufValue::UInt(v)) if is_context_length => metadata.context_length = Some(v as u32),
+            _ => {}
+        }
+    }
+
+    Some(metadata)
+}
+
+enum GgufValue {
+    Str(String),
+    UInt(u64),
+    Other,
+}
+
+fn read_gguf_value(file: &mut File, value_type: u32) -> Option<GgufValue> {
+    match value_type {
+        0 => read_u8(file).map(|v| GgufValue::UInt(v as u64)),
+        1 => skip_bytes(file, 1).map(|_| GgufValue::Other),
+        2 => read_u16(file).map(|v| GgufValue::UInt(v as u64)),
+        3 => skip_bytes(file, 2).map(|_| GgufValue::Other),
+        4 => read_u32(file).map(|v| GgufValue::UInt(v as u64)),
+        5 => skip_bytes(file, 4).map(|_| GgufValue::Other),
+        6 => skip_bytes(file, 4).map(|_| GgufValue::Other),
+        7 => skip_bytes(file, 1).map(|_| GgufValue::Other),
+        8 => read_gguf_string(file).map(GgufValue::Str),
+        9 => skip_gguf_array(file),
+        10 => read_u64(file).map(GgufValue::UInt),
+        11 => skip_bytes(file, 8).map(|_| GgufValue::Other),
+        12 => skip_bytes(file, 8).map(|_| GgufValue::Other),
+        _ => None,
+    }
+}
+
+fn skip_gguf_value(file: &mut File, value_type: u32) -> Option<()> {
+    read_gguf_value(file, value_type).map(|_| ())
+}
+
+fn skip_gguf_array(file: &mut File) -> Option<GgufValue> {
+    let elem_type = read_u32(file)?;
+    let count = read_u64(file)?;
+    if count > MAX_ENTRY_COUNT {
+        return None;
+    }
+    for _ in 0..count {
+        match elem_type {
+            0 | 1 | 7 => skip_bytes(file, 1)?,
+            2 | 3 => skip_bytes(file, 2)?,
+            4 | 5 | 6 => skip_bytes(file, 4)?,
+            8 => {
+                read_gguf_string(file)?;
+            }
+            10 | 11 | 12 => skip_bytes(file, 8)?,
+            _ => return None,
+        }
+    }
+    Some(GgufValue::Other)
+}
+
+fn read_gguf_string(file: &mut File) -> Option<String> {
+    let len = read_u64(file)?;
+    if len > MAX_STRING_LEN {
+        return None;
+    }
+    let mut buf = vec![0u8; len as usize];
+    file.read_exact(&mut buf).ok()?;
+    String::from_utf8(buf).ok()
+}
+
+fn read_u8(file: &mut File) -> Option<u8> {
+    let mut buf = [0u8; 1];
+    file.read_exact(&mut buf).ok()?;
+    Some(buf[0])
+}
+
+fn read_u16(file: &mut File) -> Option<u16> {
+    let mut buf = [0u8; 2];
+    file.read_exact(&mut buf).ok()?;
+    Some(u16::from_le_bytes(buf))
+}
+
+fn read_u32(file: &mut File) -> Option<u32> {
+    let mut buf = [0u8; 4];
+    file.read_exact(&mut buf).ok()?;
+    Some(u32::from_le_bytes(buf))
+}
+
+fn read_u64(file: &mut File) -> Option<u64> {
+    let mut buf = [0u8; 8];
+    file.read_exact(&mut buf).ok()?;
+    Some(u64::from_le_bytes(buf))
+}
+
+fn skip_bytes(file: &mut File, n: u64) -> Option<()> {
+    file.seek(SeekFrom::Current(n as i64)).ok()?;
+    Some(())
+}
+
+/// 将 `general.file_type` 的数值映射为常见的人类可读量化名称
+fn file_type_name(value: u64) -> String {
+    match value {
+        0 => "F32".to_string(),
+        1 => "F16".to_string(),
+        2 => "Q4_0".to_string(),
+        3 => "Q4_1".to_string(),
+        7 => "Q8_0".to_string(),
+        8 => "Q5_0".to_string(),
+        9 => "Q5_1".to_string(),
+        10 => "Q2_K".to_string(),
+        11 => "Q3_K_S".to_string(),
+        12 => "Q3_K_M".to_string(),
+        13 => "Q3_K_L".to_string(),
+        14 => "Q4_K_S".to_string(),
+        15 => "Q4_K_M".to_string(),
+        16 => "Q5_K_S".to_string(),
+        17 => "Q5_K_M".to_string(),
+        18 => "Q6_K".to_string(),
+        19 => "IQ2_XXS".to_string(),
+        20 => "IQ2_XS".to_string(),
+        21 => "Q2_K_S".to_string(),
+        22 => "IQ3_XS".to_string(),
+        23 => "IQ3_XXS".to_string(),
+        24 => "IQ1_S".to_string(),
+        25 => "IQ4_NL".to_string(),
+        26 => "IQ3_S".to_string(),
+        27 => "IQ3_M".to_string(),
+        28 => "IQ2_S".to_string(),
+        29 => "IQ2_M".to_string(),
+        30 => "IQ4_XS".to_string(),
+        31 => "IQ1_M".to_string(),
+        32 => "BF16".to_string(),
+        other => format!("ftype:{other}"),
+    }
+}
@@ -3,9 +3,17 @@ pub mod api;
 #[cfg(feature = "chat")]
 pub mod chat;
 pub mod cli;
+pub mod config;
+pub mod conversation_store;
 pub mod discover;
 pub mod engine;
+pub mod env;
+pub mod gguf;
+pub mod safetensors;
 pub mod template;
+#[cfg(feature = "chat")]
+pub mod tools;
+pub mod vectorstore;
 
 //#[cfg(feature = "engine-hf")]
 //compile_error!("The `engine-hf` feature is not supported yet.");
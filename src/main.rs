@@ -35,11 +35,17 @@ fn infer(args: &cli::InferArgs) -> Result<(), Box<dyn std::error::Error>> {
             top_k: args.top_k.unwrap_or(40),
             top_p: args.top_p.unwrap_or(0.9),
             repeat_penalty: args.repeat_penalty.unwrap_or(1.1),
+            min_p: args.min_p.unwrap_or(0.0),
+            typical_p: args.typical_p.unwrap_or(1.0),
+            seed: args.seed,
+            repeat_last_n: args.repeat_last_n.unwrap_or(64),
+            ..Default::default()
         },
         &model_path,
     )?;
 
     engine.infer(
+        None,
         &prompt,
         None,
         def_callback!(|token| {
@@ -62,6 +68,7 @@ fn list_models() -> Result<(), Box<dyn std::error::Error>> {
             let model_type = match model.format {
                 discover::ModelType::Gguf => "GGUF",
                 discover::ModelType::Transformers => "Transformers",
+                discover::ModelType::Remote => "Remote",
             };
 
             // 智能单位显示
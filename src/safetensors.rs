@@ -0,0 +1,68 @@
+//! 轻量级 safetensors 头部解析器：只读取文件开头的 JSON 头部，
+//! 从不加载张量数据本身。
+
+use serde_json::Value;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// 头部 JSON 长度上限，防止损坏文件诱导我们分配天文数字大小的缓冲区
+const MAX_HEADER_LEN: u64 = 50 * 1024 * 1024;
+
+/// 从 safetensors 头部提取出的、对模型发现有用的元数据子集
+#[derive(Debug, Clone, Default)]
+pub struct SafetensorsMetadata {
+    /// `__metadata__.format` 或其中量化相关字段给出的量化提示
+    pub quant_hint: Option<String>,
+    /// `__metadata__` 中常见的架构字段（如 `model_type`/`architecture`）
+    pub architecture: Option<String>,
+    /// 任意一个张量条目的 `dtype`，近似代表整个文件的存储精度
+    pub dtype: Option<String>,
+}
+
+/// 解析 safetensors 文件开头的 JSON 头部。
+///
+/// 返回 `None` 表示头部不是合法 JSON（不是 safetensors 文件）；
+/// 返回 `Some` 则代表"已确认是 safetensors"，具体字段是否有值取决于
+/// `__metadata__` 和张量条目里实际写了什么。
+pub fn read_safetensors_metadata(path: &Path) -> Option<SafetensorsMetadata> {
+    let mut file = File::open(path).ok()?;
+
+    let mut len_bytes = [0u8; 8];
+    file.read_exact(&mut len_bytes).ok()?;
+    let len = u64::from_le_bytes(len_bytes);
+    if len > MAX_HEADER_LEN {
+        return None;
+    }
+
+    let mut json_bytes = vec![0u8; len as usize];
+    file.read_exact(&mut json_bytes).ok()?;
+    let json_str = String::from_utf8(json_bytes).ok()?;
+    let header: Value = serde_json::from_str(&json_str).ok()?;
+    let header = header.as_object()?;
+
+    let mut metadata = SafetensorsMetadata::default();
+
+    if let Some(meta) = header.get("__metadata__").and_then(Value::as_object) {
+        metadata.architecture = meta
+            .get("model_type")
+            .or_else(|| meta.get("architecture"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        metadata.quant_hint = meta
+            .get("quant_method")
+            .or_else(|| meta.get("quantization"))
+            .or_else(|| meta.get("format"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+    }
+
+    metadata.dtype = header
+        .iter()
+        .find(|(key, _)| key.as_str() != "__metadata__")
+        .and_then(|(_, tensor)| tensor.get("dtype"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+
+    Some(metadata)
+}
@@ -36,7 +36,30 @@ fn select_engine(model: &Model) -> RenderFunc {
         ModelType::Transformers => {
             compile_error!("Transformers models require the `engine-hf` feature to be enabled.")
         }
+
+        // 远程模型的 prompt 渲染发生在上游服务器里，这里只需要原样透传
+        // 最后一条消息（或 Prompt 字段），不套用任何本地聊天模板。
+        ModelType::Remote => {
+            return render_passthrough;
+        }
+    }
+}
+
+fn render_passthrough(
+    _template: &str,
+    data: &TemplateData,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if let Some(prompt) = &data.prompt {
+        return Ok(prompt.clone());
     }
+    let content = data
+        .messages
+        .as_ref()
+        .and_then(|messages| messages.last())
+        .and_then(|message| message.content.as_ref())
+        .map(|content| content.as_text())
+        .unwrap_or_default();
+    Ok(content)
 }
 
 #[cfg(any(feature = "tpl-gtmpl", feature = "tpl-gotpl"))]
@@ -64,6 +64,42 @@ impl ToolProperty {
             _ => "any".to_string(),
         }
     }
+
+    /// 校验单个参数值是否匹配这条属性声明的类型和（如果有的话）枚举取值
+    fn validate(&self, name: &str, value: &Value) -> Result<(), String> {
+        let type_matches = match self.property_type.as_str() {
+            "string" => value.is_string(),
+            "number" => value.is_number(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "boolean" => value.is_boolean(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            _ => true,
+        };
+        if !type_matches {
+            return Err(format!(
+                "argument `{}` must be of type `{}`",
+                name, self.property_type
+            ));
+        }
+
+        if let Some(enum_values) = &self.enum_values {
+            if !enum_values.is_empty() {
+                let matches = value
+                    .as_str()
+                    .map(|s| enum_values.iter().any(|v| v == s))
+                    .unwrap_or(false);
+                if !matches {
+                    return Err(format!(
+                        "argument `{}` must be one of {:?}",
+                        name, enum_values
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 // 工具函数参数定义
@@ -77,6 +113,37 @@ pub struct FunctionParameters {
     pub required: Option<Vec<String>>,
 }
 
+impl FunctionParameters {
+    /// 校验模型生成的 `arguments` 是否满足这份参数声明：必填字段必须出现，
+    /// 出现的字段类型必须和 `ToolProperty::property_type` 匹配
+    /// （枚举值还会额外检查是否在 `enum_values` 里）。只做浅层检查，不递归
+    /// 进 `object`/`array` 内部的结构。
+    pub fn validate(&self, arguments: &Value) -> Result<(), String> {
+        let object = arguments
+            .as_object()
+            .ok_or_else(|| "arguments must be a JSON object".to_string())?;
+
+        if let Some(required) = &self.required {
+            for name in required {
+                if !object.contains_key(name) {
+                    return Err(format!("missing required argument `{}`", name));
+                }
+            }
+        }
+
+        let Some(properties) = &self.properties else {
+            return Ok(());
+        };
+        for (name, value) in object {
+            let Some(property) = properties.get(name) else {
+                continue;
+            };
+            property.validate(name, value)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Function {
     #[serde(rename = "name")]
@@ -105,12 +172,74 @@ pub struct Tool {
     pub function: Function,
 }
 
+/// 非文本内容片段，目前只有图片（OpenAI vision API 的 `image_url` 形状）。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImageUrlContent {
+    pub url: String,
+}
+
+/// 一条消息里的单个内容片段，和 OpenAI vision API 的 content part 对齐：
+/// `type` 字段区分文本和图片。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrlContent },
+}
+
+/// 消息内容：绝大多数消息仍然只是一段纯文本，序列化成普通 JSON 字符串，兼容
+/// 所有只认字符串 `content` 的旧调用方（模板、历史记录、SQLite 存储）；只有
+/// 需要携带图片等非文本片段的消息才会用到 `Parts`。`#[serde(untagged)]`
+/// 让反序列化时先尝试当作字符串，不行再当作片段数组。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+impl MessageContent {
+    /// 抹平成纯文本：`Text` 原样返回，`Parts` 拼接其中的文本片段；模板引擎
+    /// 和历史记录打印都不理解多模态内容，图片片段退化成一个占位符。
+    pub fn as_text(&self) -> String {
+        match self {
+            MessageContent::Text(text) => text.clone(),
+            MessageContent::Parts(parts) => parts
+                .iter()
+                .map(|part| match part {
+                    ContentPart::Text { text } => text.clone(),
+                    ContentPart::ImageUrl { .. } => "[image]".to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+impl Default for MessageContent {
+    fn default() -> Self {
+        MessageContent::Text(String::new())
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        MessageContent::Text(text)
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(text: &str) -> Self {
+        MessageContent::Text(text.to_string())
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Message {
     #[serde(rename = "Role")]
     pub role: String,
     #[serde(skip_serializing_if = "Option::is_none", rename = "Content")]
-    pub content: Option<String>,
+    pub content: Option<MessageContent>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "ToolCalls")]
     pub tool_calls: Option<Vec<ToolCall>>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "Name")]
@@ -226,16 +355,21 @@ pub fn collate_messages(messages: Vec<Message>) -> (Option<String>, Vec<Message>
 
     for msg in messages {
         if msg.role == "system" {
-            if let Some(content) = msg.content {
-                system_parts.push(content);
+            if let Some(content) = &msg.content {
+                system_parts.push(content.as_text());
             }
             continue;
         }
 
         if let Some(last) = collated.last_mut() {
             if last.role == msg.role && msg.role != "tool" {
-                if let (Some(last_content), Some(msg_content)) = (&mut last.content, &msg.content) {
-                    *last_content = format!("{}\n\n{}", last_content, msg_content);
+                // 只有两边都是纯文本才原地拼接；带图片片段的消息保留各自的
+                // `Parts`，分开作为独立消息处理。
+                if let (Some(MessageContent::Text(last_text)), Some(MessageContent::Text(msg_text))) =
+                    (&mut last.content, &msg.content)
+                {
+                    last_text.push_str("\n\n");
+                    last_text.push_str(msg_text);
                     continue;
                 }
             }
@@ -252,3 +386,115 @@ pub fn collate_messages(messages: Vec<Message>) -> (Option<String>, Vec<Message>
 
     (system, collated)
 }
+
+/// 把一段可能是 `{"name": ..., "arguments": {...}}` 的文本解析成一个
+/// `ToolCall`；不是合法 JSON、或者没有 `name` 字段就返回 `None`。容忍模型在
+/// JSON 后面多吐了几个字符（解释性文字、多余的标点）——先按严格 JSON 解析，
+/// 失败再截到最后一个 `}` 重试一次。
+fn parse_json_tool_call(body: &str) -> Option<ToolCall> {
+    let body = body.trim();
+    let parsed = serde_json::from_str::<Value>(body)
+        .ok()
+        .or_else(|| serde_json::from_str::<Value>(&body[..=body.rfind('}')?]).ok())?;
+    let name = parsed["name"].as_str()?;
+    Some(ToolCall {
+        id: Some(format!("call_{}", uuid::Uuid::new_v4())),
+        function: Function {
+            name: name.to_string(),
+            description: None,
+            parameters: None,
+            arguments: parsed.get("arguments").cloned(),
+        },
+    })
+}
+
+/// `s` 两端去掉空白后如果还有内容就包成 `Some`，否则 `None`——用来把"可能是
+/// 空字符串的剩余文本"规整成调用方更方便处理的 `Option`。
+fn non_empty_trimmed(s: &str) -> Option<String> {
+    let s = s.trim();
+    if s.is_empty() { None } else { Some(s.to_string()) }
+}
+
+/// 从模型生成的文本里提取工具调用并解析为 `ToolCall`，同时把不属于任何工具
+/// 调用的文本原样保留下来，而不是一旦命中工具调用就把其余内容全部丢弃——
+/// 模型经常会先说一句话再发起调用，那句话不该在响应里消失。
+///
+/// 按优先级依次尝试三种模型常用的输出形态（互斥：标签形式命中过就不再去找
+/// 代码块或裸对象）：
+/// 1. Qwen 风格的 `<tool_call>...</tool_call>` 标签，可重复出现多次；
+/// 2. 没有标签、但用三个反引号围起来的 JSON 代码块（可带 `json` 语言标注）；
+/// 3. 整段输出本身就是一个以 `{` 开头的 JSON 对象（不带围栏）。
+/// 解析失败的块会原样保留在剩余文本里，而不是静默丢弃。遇到一个还没闭合的
+/// `<tool_call>`（流式生成中途被截断时常见）会把它之后的内容整体丢弃而不是
+/// 当成普通文本返回——调用方应该继续缓冲、等拿到完整文本再重新解析一次，
+/// 避免把半截标签/JSON 泄露给客户端。
+/// 每个解析出来的调用都会带上一个生成的 id（OpenAI `call_...` 的命名习惯），
+/// 调用方不需要再自己兜底生成。
+pub fn parse_tool_calls(text: &str) -> (Option<String>, Vec<ToolCall>) {
+    const OPEN_TAG: &str = "<tool_call>";
+    const CLOSE_TAG: &str = "</tool_call>";
+
+    let mut calls = Vec::new();
+    let mut leftover = String::new();
+    let mut rest = text;
+    let mut truncated = false;
+    while let Some(start) = rest.find(OPEN_TAG) {
+        leftover.push_str(&rest[..start]);
+        let after_open = &rest[start + OPEN_TAG.len()..];
+        let Some(end) = after_open.find(CLOSE_TAG) else {
+            truncated = true;
+            rest = "";
+            break;
+        };
+        let body = after_open[..end].trim();
+        rest = &after_open[end + CLOSE_TAG.len()..];
+
+        match parse_json_tool_call(body) {
+            Some(call) => calls.push(call),
+            None => leftover.push_str(&format!("{}{}{}", OPEN_TAG, body, CLOSE_TAG)),
+        }
+    }
+    if !truncated {
+        leftover.push_str(rest);
+    }
+    if !calls.is_empty() || truncated {
+        return (non_empty_trimmed(&leftover), calls);
+    }
+
+    // 没找到标签块：试试栏栅代码块（```json ... ``` 或裸 ```...```）。
+    const FENCE: &str = "```";
+    let mut leftover = String::new();
+    let mut rest = text;
+    let mut truncated = false;
+    while let Some(start) = rest.find(FENCE) {
+        leftover.push_str(&rest[..start]);
+        let after_open = &rest[start + FENCE.len()..];
+        let after_open = after_open.strip_prefix("json").unwrap_or(after_open);
+        let Some(end) = after_open.find(FENCE) else {
+            truncated = true;
+            rest = "";
+            break;
+        };
+        let body = after_open[..end].trim();
+        rest = &after_open[end + FENCE.len()..];
+
+        match parse_json_tool_call(body) {
+            Some(call) => calls.push(call),
+            None => leftover.push_str(&format!("{}{}{}", FENCE, body, FENCE)),
+        }
+    }
+    if !truncated {
+        leftover.push_str(rest);
+    }
+    if !calls.is_empty() {
+        return (non_empty_trimmed(&leftover), calls);
+    }
+
+    // 再退一步：整段回复本身就是裸的 JSON 对象，模型没有加任何围栏或标签。
+    if text.trim_start().starts_with('{') {
+        if let Some(call) = parse_json_tool_call(text) {
+            return (None, vec![call]);
+        }
+    }
+    (non_empty_trimmed(text), calls)
+}
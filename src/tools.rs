@@ -0,0 +1,110 @@
+//! 本地可执行工具的注册表：用名称、JSON-schema 参数和 Rust 闭包声明一个工具，
+//! 供 `ChatSession` 的多步 function-calling 循环按名称查找并在本地直接调用。
+
+use crate::template::{Function, FunctionParameters, Tool};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// 工具处理函数：接收 OpenAI 风格的 `arguments` JSON，返回要喂回模型的文本结果
+pub type ToolHandler = Box<dyn Fn(&Value) -> Result<String, String> + Send + Sync>;
+
+struct RegisteredTool {
+    definition: Tool,
+    handler: ToolHandler,
+}
+
+/// 本地工具注册表
+#[derive(Default)]
+pub struct ToolRegistry {
+    tools: HashMap<String, RegisteredTool>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 内置的示例工具集，目前只有一个获取本机当前时间的工具
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        registry.register(
+            "get_current_time",
+            "Get the current local date and time as a Unix timestamp.",
+            FunctionParameters {
+                param_type: "object".to_string(),
+                properties: Some(HashMap::new()),
+                required: None,
+            },
+            |_arguments| {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_err(|e| e.to_string())?;
+                Ok(now.as_secs().to_string())
+            },
+        );
+        registry
+    }
+
+    /// 注册一个工具：`name`/`description`/`parameters` 会被渲染进 prompt 里的工具列表，
+    /// `handler` 在模型发起调用时实际执行
+    pub fn register<F>(&mut self, name: &str, description: &str, parameters: FunctionParameters, handler: F)
+    where
+        F: Fn(&Value) -> Result<String, String> + Send + Sync + 'static,
+    {
+        let definition = Tool {
+            tool_type: "function".to_string(),
+            function: Function {
+                name: name.to_string(),
+                description: Some(description.to_string()),
+                parameters: Some(parameters),
+                arguments: None,
+            },
+        };
+        self.tools.insert(
+            name.to_string(),
+            RegisteredTool {
+                definition,
+                handler: Box::new(handler),
+            },
+        );
+    }
+
+    /// 把所有已注册工具转换为可以直接塞进 `TemplateData::tools` 的定义列表
+    pub fn tool_defs(&self) -> Option<Vec<Tool>> {
+        if self.tools.is_empty() {
+            return None;
+        }
+        Some(self.tools.values().map(|t| t.definition.clone()).collect())
+    }
+
+    /// 按名称调用一个已注册工具；调用前先按工具声明的 `FunctionParameters`
+    /// 校验 `arguments`，模型生成的参数类型不对或缺了必填字段时直接报错，
+    /// 不会把这种明显错误的调用转发给 handler。handler 本身 panic 时也会被
+    /// 捕获并转成一条错误消息，而不是把调用它的线程（在 `engine::agent` 里是
+    /// 并发跑多个工具调用的线程池的一员）直接带崩。
+    pub fn call(&self, name: &str, arguments: &Value) -> Result<String, String> {
+        let registered = self
+            .tools
+            .get(name)
+            .ok_or_else(|| format!("Unknown tool: {}", name))?;
+        if let Some(parameters) = &registered.definition.function.parameters {
+            parameters.validate(arguments)?;
+        }
+        let handler = &registered.handler;
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| handler(arguments)))
+            .unwrap_or_else(|panic| Err(format!("Tool '{}' panicked: {}", name, panic_message(&panic))))
+    }
+}
+
+/// 从 `catch_unwind` 捕获的 panic payload 里提取一条人可读的消息；Rust 的 panic
+/// payload 几乎总是 `&str` 或 `String`（`panic!("...")` / `.unwrap()` 的默认
+/// hook），两者都取不出来就退化成一个占位描述。
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = panic.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = panic.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
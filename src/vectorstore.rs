@@ -0,0 +1,325 @@
+//! 基于 HNSW（分层可导航小世界图）的内存向量库，用于检索增强对话：
+//! 在推理前取出与当前输入最相关的若干段落，拼进 `TemplateData`。
+//!
+//! 算法遵循 Malkov & Yashunin 的原始设计：每个新节点被赋予一个随机的
+//! 最高层 `floor(-ln(U) * mL)`（`U` 为 (0,1] 上的均匀分布），插入时从入口点
+//! 贪心下降到该层，再从该层到第 0 层依次用启发式规则挑选 `M` 个邻居；
+//! 查询时只在第 0 层做候选列表大小为 `ef` 的 beam search。
+
+use lazy_static::lazy_static;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+lazy_static! {
+    /// 按 conversation id 隔离的向量库：每个对话各自维护一份检索上下文，互不
+    /// 污染。只活在进程内存里——不像 `ConversationStore` 那样落盘，重启后
+    /// 需要的话可以从历史消息里重新索引一遍。
+    static ref CONVERSATION_STORES: Mutex<HashMap<String, VectorStore>> =
+        Mutex::new(HashMap::new());
+}
+
+/// 把一段文本的 embedding 存进某个对话专属的向量库里，供该对话后续轮次检索。
+pub fn index_turn(conversation_id: &str, vector: Vec<f32>, payload: String) {
+    let mut stores = CONVERSATION_STORES.lock().unwrap();
+    stores
+        .entry(conversation_id.to_string())
+        .or_insert_with(VectorStore::new)
+        .insert(vector, payload);
+}
+
+/// 检索某个对话里和 `query` 最相关的 `k` 段历史文本；该对话还没索引过任何
+/// 内容（或从未出现过）时返回空列表。
+pub fn retrieve_context(conversation_id: &str, query: &[f32], k: usize) -> Vec<String> {
+    CONVERSATION_STORES
+        .lock()
+        .unwrap()
+        .get(conversation_id)
+        .map(|store| store.get_context(query, k))
+        .unwrap_or_default()
+}
+
+/// HNSW 构建/查询使用的超参数
+#[derive(Debug, Clone)]
+pub struct HnswConfig {
+    /// 每个节点在每一层保留的邻居数量
+    pub m: usize,
+    /// 插入时的候选列表大小，越大图质量越好但插入越慢
+    pub ef_construction: usize,
+    /// 查询时的候选列表大小
+    pub ef_search: usize,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        HnswConfig {
+            m: 16,
+            ef_construction: 200,
+            ef_search: 64,
+        }
+    }
+}
+
+struct Node {
+    vector: Vec<f32>,
+    payload: String,
+    /// `neighbors[layer]` 是该节点在这一层的邻居下标列表
+    neighbors: Vec<Vec<usize>>,
+}
+
+/// 一个简单的 xorshift64* 生成器，避免仅为随机层数引入新的依赖
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Self {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .subsec_nanos() as u64
+            | 1;
+        Rng(seed)
+    }
+
+    /// (0, 1] 上的均匀分布
+    fn next_open01(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        ((self.0 >> 11) as f64 + 1.0) / (1u64 << 53) as f64
+    }
+}
+
+/// 内存向量库：索引 embedding 并支持近似最近邻检索
+pub struct VectorStore {
+    config: HnswConfig,
+    nodes: Vec<Node>,
+    entry_point: Option<usize>,
+    /// `mL = 1 / ln(M)`，控制新节点随机层数的期望衰减速度
+    level_mult: f64,
+    rng: Rng,
+}
+
+impl VectorStore {
+    pub fn new() -> Self {
+        Self::with_config(HnswConfig::default())
+    }
+
+    pub fn with_config(config: HnswConfig) -> Self {
+        let level_mult = 1.0 / (config.m as f64).ln();
+        VectorStore {
+            config,
+            nodes: Vec::new(),
+            entry_point: None,
+            level_mult,
+            rng: Rng::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// 插入一个向量及其关联的原文段落
+    pub fn insert(&mut self, vector: Vec<f32>, payload: String) {
+        let level = self.random_level();
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            vector,
+            payload,
+            neighbors: vec![Vec::new(); level + 1],
+        });
+
+        let Some(entry_point) = self.entry_point else {
+            self.entry_point = Some(id);
+            return;
+        };
+
+        let query = self.nodes[id].vector.clone();
+        let entry_level = self.nodes[entry_point].neighbors.len() - 1;
+        let mut nearest = entry_point;
+
+        // 从入口层贪心下降到 level + 1 层，每层只留下当前最近点作为下一层的入口
+        for layer in ((level + 1)..=entry_level).rev() {
+            nearest = self.greedy_closest(&query, nearest, layer);
+        }
+
+        // 从 min(level, entry_level) 到第 0 层，用 ef_construction 大小的候选列表连接邻居
+        for layer in (0..=level.min(entry_level)).rev() {
+            let candidates = self.search_layer(&query, nearest, self.config.ef_construction, layer);
+            let selected = self.select_neighbors(&query, &candidates, self.config.m);
+
+            for &neighbor in &selected {
+                self.connect(id, neighbor, layer);
+                self.connect(neighbor, id, layer);
+                self.prune_neighbors(neighbor, layer);
+            }
+            if let Some(&(closest, _)) = candidates.first() {
+                nearest = closest;
+            }
+        }
+
+        if level > entry_level {
+            self.entry_point = Some(id);
+        }
+    }
+
+    /// 返回与 `query` 最相关的前 `k` 个段落
+    pub fn search(&self, query: &[f32], k: usize) -> Vec<(f32, &str)> {
+        let Some(entry_point) = self.entry_point else {
+            return Vec::new();
+        };
+
+        let top_layer = self.nodes[entry_point].neighbors.len() - 1;
+        let mut nearest = entry_point;
+        for layer in (1..=top_layer).rev() {
+            nearest = self.greedy_closest(query, nearest, layer);
+        }
+
+        let ef = self.config.ef_search.max(k);
+        let mut candidates = self.search_layer(query, nearest, ef, 0);
+        candidates.truncate(k);
+        candidates
+            .into_iter()
+            .map(|(id, dist)| (dist, self.nodes[id].payload.as_str()))
+            .collect()
+    }
+
+    /// 检索 `query` 对应向量最相关的前 `k` 段文本，供拼接进 prompt
+    pub fn get_context(&self, query: &[f32], k: usize) -> Vec<String> {
+        self.search(query, k)
+            .into_iter()
+            .map(|(_, payload)| payload.to_string())
+            .collect()
+    }
+
+    fn random_level(&mut self) -> usize {
+        let u = self.rng.next_open01();
+        (-u.ln() * self.level_mult).floor() as usize
+    }
+
+    /// 在某一层上从 `start` 出发贪心走到局部最近点（用于逐层下降）
+    fn greedy_closest(&self, query: &[f32], start: usize, layer: usize) -> usize {
+        let mut current = start;
+        let mut current_dist = cosine_distance(query, &self.nodes[current].vector);
+        loop {
+            let mut improved = false;
+            for &neighbor in neighbors_at(&self.nodes[current], layer) {
+                let dist = cosine_distance(query, &self.nodes[neighbor].vector);
+                if dist < current_dist {
+                    current = neighbor;
+                    current_dist = dist;
+                    improved = true;
+                }
+            }
+            if !improved {
+                return current;
+            }
+        }
+    }
+
+    /// 在某一层上以动态候选列表大小 `ef` 做 beam search，
+    /// 返回按距离升序排序的 `(node_id, distance)` 列表
+    fn search_layer(
+        &self,
+        query: &[f32],
+        start: usize,
+        ef: usize,
+        layer: usize,
+    ) -> Vec<(usize, f32)> {
+        let start_dist = cosine_distance(query, &self.nodes[start].vector);
+        let mut visited: HashSet<usize> = HashSet::from([start]);
+        // 待探索的候选（按距离升序遍历）与已找到的结果集（始终保持排序）
+        let mut candidates: Vec<(usize, f32)> = vec![(start, start_dist)];
+        let mut found: Vec<(usize, f32)> = vec![(start, start_dist)];
+
+        while let Some(&(current, current_dist)) = candidates.first() {
+            candidates.remove(0);
+            let worst_found = found.last().map(|&(_, d)| d).unwrap_or(f32::MAX);
+            if current_dist > worst_found && found.len() >= ef {
+                break;
+            }
+
+            for &neighbor in neighbors_at(&self.nodes[current], layer) {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let dist = cosine_distance(query, &self.nodes[neighbor].vector);
+                let worst_found = found.last().map(|&(_, d)| d).unwrap_or(f32::MAX);
+                if found.len() < ef || dist < worst_found {
+                    insert_sorted(&mut candidates, (neighbor, dist));
+                    insert_sorted(&mut found, (neighbor, dist));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+
+        found
+    }
+
+    /// 邻居选择启发式：只保留比任何已选邻居都更接近新节点的候选，
+    /// 避免在密集簇里塞进大量彼此冗余的连接
+    fn select_neighbors(&self, query: &[f32], candidates: &[(usize, f32)], m: usize) -> Vec<usize> {
+        let mut selected: Vec<usize> = Vec::with_capacity(m);
+        for &(candidate, candidate_dist) in candidates {
+            if selected.len() >= m {
+                break;
+            }
+            let closer_to_selected = selected.iter().any(|&s| {
+                cosine_distance(&self.nodes[candidate].vector, &self.nodes[s].vector) < candidate_dist
+            });
+            if !closer_to_selected {
+                selected.push(candidate);
+            }
+        }
+        selected
+    }
+
+    fn connect(&mut self, from: usize, to: usize, layer: usize) {
+        let neighbors = &mut self.nodes[from].neighbors[layer];
+        if !neighbors.contains(&to) {
+            neighbors.push(to);
+        }
+    }
+
+    /// 某个节点在某一层的邻居数超过 `m` 时，只保留离它最近的 `m` 个
+    fn prune_neighbors(&mut self, node: usize, layer: usize) {
+        if self.nodes[node].neighbors[layer].len() <= self.config.m {
+            return;
+        }
+        let vector = self.nodes[node].vector.clone();
+        let mut ranked: Vec<(usize, f32)> = self.nodes[node].neighbors[layer]
+            .iter()
+            .map(|&id| (id, cosine_distance(&vector, &self.nodes[id].vector)))
+            .collect();
+        ranked.sort_by(|a, b| a.1.total_cmp(&b.1));
+        ranked.truncate(self.config.m);
+        self.nodes[node].neighbors[layer] = ranked.into_iter().map(|(id, _)| id).collect();
+    }
+}
+
+impl Default for VectorStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn neighbors_at(node: &Node, layer: usize) -> &[usize] {
+    node.neighbors.get(layer).map(Vec::as_slice).unwrap_or(&[])
+}
+
+fn insert_sorted(list: &mut Vec<(usize, f32)>, item: (usize, f32)) {
+    let pos = list.partition_point(|&(_, d)| d < item.1);
+    list.insert(pos, item);
+}
+
+/// 余弦距离 `1 - cosine_similarity`；向量已在 `embed()` 中归一化，
+/// 因此点积本身就是余弦相似度
+fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    1.0 - dot
+}